@@ -0,0 +1,164 @@
+//! The level history and the hand-off of frames from the analysis thread, against what
+//! they replaced: a `Vec` shifted on every push, behind a `Mutex` that the drawing thread
+//! held while drawing.
+//!
+//! Run with `cargo bench --bench history`.
+
+use audio_vis::{feed, Frame, History, Registry};
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use std::{
+    sync::{mpsc, Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+const BANDS: usize = 100;
+const DRAW_TIME: Duration = Duration::from_millis(1); // Stand-in for drawing a frame
+
+/// A stereo frame with a full spectrum.
+fn frame() -> Frame {
+    Frame {
+        level: 0.5,
+        channel_levels: vec![0.5; 2],
+        channel_peak_levels: vec![0.7; 2],
+        duration: 0.05,
+        spectrum: vec![0.5; BANDS],
+        ..Frame::default()
+    }
+}
+
+/// The history as it was, shifting everything along once it is full.
+struct ShiftingHistory {
+    capacity: usize,
+    levels: Vec<f32>,
+    channel_levels: Vec<Vec<f32>>,
+    spectra: Vec<Vec<f32>>,
+}
+
+impl ShiftingHistory {
+    fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            levels: Vec::with_capacity(capacity + 1),
+            channel_levels: Vec::new(),
+            spectra: Vec::with_capacity(capacity + 1),
+        }
+    }
+
+    fn push(&mut self, frame: Frame) {
+        Self::push_capped(&mut self.levels, frame.level, self.capacity);
+        self.channel_levels
+            .resize_with(frame.channel_levels.len(), Vec::new);
+        for (levels, &level) in self.channel_levels.iter_mut().zip(&frame.channel_levels) {
+            Self::push_capped(levels, level, self.capacity);
+        }
+        Self::push_capped(&mut self.spectra, frame.spectrum, self.capacity);
+    }
+
+    fn push_capped<T>(items: &mut Vec<T>, item: T, capacity: usize) {
+        items.push(item);
+        if items.len() > capacity {
+            items.remove(0);
+        }
+    }
+}
+
+fn push(c: &mut Criterion) {
+    let mut group = c.benchmark_group("push");
+    for capacity in [100, 1_000, 10_000] {
+        group.bench_with_input(
+            BenchmarkId::new("shifting", capacity),
+            &capacity,
+            |b, &capacity| {
+                let mut history = ShiftingHistory::new(capacity);
+                for _ in 0..capacity {
+                    history.push(frame());
+                }
+                b.iter(|| history.push(black_box(frame())));
+            },
+        );
+        group.bench_with_input(
+            BenchmarkId::new("history", capacity),
+            &capacity,
+            |b, &capacity| {
+                let mut history = History::new(capacity);
+                for _ in 0..capacity {
+                    history.push(frame());
+                }
+                b.iter(|| history.push(black_box(frame())));
+            },
+        );
+    }
+    group.finish();
+}
+
+/// Time the analysis thread takes to hand over a frame while the history is being drawn.
+fn handoff(c: &mut Criterion) {
+    let mut group = c.benchmark_group("handoff");
+    group.sample_size(20);
+
+    // The drawing thread signals once it has started a draw
+    let (start, draws) = mpsc::channel::<()>();
+    let (started, drawing) = mpsc::channel();
+    let history = Arc::new(Mutex::new(ShiftingHistory::new(1_000)));
+    let drawer = {
+        let history = Arc::clone(&history);
+        thread::spawn(move || {
+            for () in draws {
+                let history = history.lock().unwrap();
+                started.send(()).unwrap();
+                black_box(history.levels.len());
+                thread::sleep(DRAW_TIME);
+            }
+        })
+    };
+    group.bench_function("mutex", |b| {
+        b.iter_custom(|iters| {
+            let mut total = Duration::ZERO;
+            for _ in 0..iters {
+                start.send(()).unwrap();
+                drawing.recv().unwrap();
+                let time = Instant::now();
+                history.lock().unwrap().push(black_box(frame()));
+                total += time.elapsed();
+            }
+            total
+        })
+    });
+    drop(start);
+    drawer.join().unwrap();
+
+    let (start, draws) = mpsc::channel::<()>();
+    let (started, drawing) = mpsc::channel();
+    let (mut sender, mut receiver) = feed::channel(1_000);
+    let drawer = thread::spawn(move || {
+        let mut history = History::new(1_000);
+        let mut visualizers = Registry::default();
+        for () in draws {
+            receiver.receive(&mut history, &mut visualizers);
+            started.send(()).unwrap();
+            black_box(history.levels().len());
+            thread::sleep(DRAW_TIME);
+        }
+    });
+    group.bench_function("feed", |b| {
+        b.iter_custom(|iters| {
+            let mut total = Duration::ZERO;
+            for _ in 0..iters {
+                start.send(()).unwrap();
+                drawing.recv().unwrap();
+                let time = Instant::now();
+                sender.send(0, black_box(frame()));
+                total += time.elapsed();
+            }
+            total
+        })
+    });
+    drop(start);
+    drawer.join().unwrap();
+
+    group.finish();
+}
+
+criterion_group!(benches, push, handoff);
+criterion_main!(benches);
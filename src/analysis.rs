@@ -0,0 +1,753 @@
+use crate::loudness::{Loudness, Meter};
+use rustfft::{num_complex::Complex, Fft, FftPlanner};
+use serde::{
+    de::{self, value::MapAccessDeserializer, MapAccess, Visitor},
+    Deserialize, Deserializer,
+};
+use std::{fmt, sync::Arc};
+
+const MIN_FREQUENCY: f32 = 20.0; // Lower edge of the first spectrum band in Hz
+const CLIP_RUN: usize = 3; // Consecutive full-scale samples counted as clipping
+const PEAK_HOLD_TIME: f32 = 1.0; // Seconds a peak-hold marker stays put
+const PEAK_FALL: f32 = 0.5; // Fraction of the scale a peak-hold marker falls per second
+
+/// Parameters shared by the analyzer and the history it feeds.
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Settings {
+    /// Number of bars: history length and spectrum band count
+    pub window_size: usize,
+    /// Chunks analysed per second of audio
+    pub refresh_rate: u32,
+    /// How levels map onto bar heights
+    pub scale: Scale,
+    /// Level in dB that maps to an empty bar
+    pub db_floor: f32,
+    /// Level in dB that maps to a full bar
+    pub db_ceiling: f32,
+    /// How quickly the levels drawn follow the signal
+    pub ballistics: Ballistics,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            window_size: 100,
+            refresh_rate: 20,
+            scale: Scale::default(),
+            db_floor: -60.0,
+            db_ceiling: 0.0,
+            ballistics: Ballistics::default(),
+        }
+    }
+}
+
+impl Settings {
+    /// Maps a dB value onto `0.0..=1.0` on the chosen scale, with the ceiling at 1.
+    pub fn normalize(&self, db: f32) -> f32 {
+        let (floor, ceiling) = (self.db_floor, self.db_ceiling);
+        let level = match self.scale {
+            Scale::Linear => 10f32.powf((db - ceiling) / 20.0),
+            Scale::Db => (db - floor) / (ceiling - floor),
+            Scale::Perceptual => {
+                let loudness = |db: f32| 2f32.powf((db - ceiling) / 10.0);
+                (loudness(db) - loudness(floor)) / (1.0 - loudness(floor))
+            }
+        };
+        level.clamp(0.0, 1.0)
+    }
+}
+
+/// How dB levels map onto bar heights.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Scale {
+    /// Amplitude, from silence to the ceiling, ignoring the floor
+    Linear,
+    /// dB, from the floor to the ceiling
+    #[default]
+    Db,
+    /// Loudness as heard, doubling every 10 dB, from the floor to the ceiling
+    Perceptual,
+}
+
+impl Scale {
+    /// Names accepted by [`Scale::named`].
+    pub const NAMES: [&'static str; 3] = ["linear", "db", "perceptual"];
+
+    pub fn named(name: &str) -> Option<Self> {
+        let scale = match name {
+            "linear" => Scale::Linear,
+            "db" => Scale::Db,
+            "perceptual" => Scale::Perceptual,
+            _ => return None,
+        };
+        Some(scale)
+    }
+}
+
+impl<'de> Deserialize<'de> for Scale {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        Scale::named(&name).ok_or_else(|| {
+            de::Error::custom(format!(
+                "unknown scale `{name}`, expected linear, db or perceptual"
+            ))
+        })
+    }
+}
+
+/// How quickly drawn levels follow the signal, as the seconds taken to cover 99% of a
+/// rise (attack) or fall (release). Zero follows it at once.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Ballistics {
+    pub attack: f32,
+    pub release: f32,
+}
+
+impl Ballistics {
+    /// Names accepted by [`Ballistics::named`].
+    pub const NAMES: [&'static str; 3] = ["none", "vu", "ppm"];
+
+    /// `none` follows the signal at once, `vu` takes 300 ms both ways like a VU meter and
+    /// `ppm` rises within 10 ms and falls over 1.7 s like a peak programme meter.
+    pub fn named(name: &str) -> Option<Self> {
+        let (attack, release) = match name {
+            "none" => (0.0, 0.0),
+            "vu" => (0.3, 0.3),
+            "ppm" => (0.01, 1.7),
+            _ => return None,
+        };
+        Some(Self { attack, release })
+    }
+
+    /// Moves a drawn level `duration` seconds of the way towards the latest one.
+    pub fn apply(&self, shown: f32, level: f32, duration: f32) -> f32 {
+        let time = if level > shown {
+            self.attack
+        } else {
+            self.release
+        };
+        if time <= 0.0 {
+            return level;
+        }
+        // An exponential approach that is 99% of the way there after `time`
+        let step = 1.0 - (-100f32.ln() * duration / time).exp();
+        shown + (level - shown) * step
+    }
+
+    fn apply_all(&self, shown: &mut Vec<f32>, levels: &[f32], duration: f32) {
+        shown.resize(levels.len(), 0.0);
+        for (shown, &level) in shown.iter_mut().zip(levels) {
+            *shown = self.apply(*shown, level, duration);
+        }
+    }
+}
+
+/// A preset name, or a table of attack and release times in milliseconds.
+impl<'de> Deserialize<'de> for Ballistics {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Default, Deserialize)]
+        #[serde(default, deny_unknown_fields)]
+        struct Times {
+            attack: f32,
+            release: f32,
+        }
+
+        struct Preset;
+
+        impl<'de> Visitor<'de> for Preset {
+            type Value = Ballistics;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "none, vu, ppm or a table of attack and release in ms")
+            }
+
+            fn visit_str<E: de::Error>(self, s: &str) -> Result<Ballistics, E> {
+                Ballistics::named(s).ok_or_else(|| {
+                    E::custom(format!(
+                        "unknown ballistics `{s}`, expected none, vu, ppm or a table of attack and release in ms"
+                    ))
+                })
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<Ballistics, A::Error> {
+                let times = Times::deserialize(MapAccessDeserializer::new(map))?;
+                if !(times.attack >= 0.0 && times.release >= 0.0) {
+                    return Err(de::Error::custom(
+                        "attack and release must be non-negative numbers of ms",
+                    ));
+                }
+                Ok(Ballistics {
+                    attack: times.attack / 1000.0,
+                    release: times.release / 1000.0,
+                })
+            }
+        }
+
+        deserializer.deserialize_any(Preset)
+    }
+}
+
+/// Analysis of one chunk of audio.
+#[derive(Clone, Debug)]
+pub struct Frame {
+    /// RMS level of the chunk in dBFS
+    pub db: f32,
+    /// `db` on the display scale, after the ballistics
+    pub level: f32,
+    /// Largest absolute sample of the chunk in dBFS
+    pub peak: f32,
+    /// RMS level of each channel in dBFS, in source order (left, right, ...)
+    pub channel_db: Vec<f32>,
+    /// `channel_db` on the display scale, after the ballistics
+    pub channel_levels: Vec<f32>,
+    /// Sample peak of each channel in dBFS
+    pub channel_peaks: Vec<f32>,
+    /// `channel_peaks` on the display scale
+    pub channel_peak_levels: Vec<f32>,
+    /// Number of runs of consecutive full-scale samples, across all channels
+    pub clips: usize,
+    /// Length of the chunk in seconds
+    pub duration: f32,
+    /// Loudness of the stream up to the end of the chunk
+    pub loudness: Loudness,
+    /// Correlation between the first two channels, from -1 (out of phase) to 1 (mono)
+    pub correlation: f32,
+    /// Level of each log-spaced frequency band on the display scale, after the
+    /// ballistics, lowest band first
+    pub spectrum: Vec<f32>,
+    /// Mono mixdown of the chunk's samples, from -1 to 1
+    pub waveform: Vec<f32>,
+}
+
+/// Silence, e.g. before the first chunk has been analysed.
+impl Default for Frame {
+    fn default() -> Self {
+        Self {
+            db: f32::NEG_INFINITY,
+            level: 0.0,
+            peak: f32::NEG_INFINITY,
+            channel_db: Vec::new(),
+            channel_levels: Vec::new(),
+            channel_peaks: Vec::new(),
+            channel_peak_levels: Vec::new(),
+            clips: 0,
+            duration: 0.0,
+            loudness: Loudness::default(),
+            correlation: 0.0,
+            spectrum: Vec::new(),
+            waveform: Vec::new(),
+        }
+    }
+}
+
+/// Computes [`Frame`]s from consecutive chunks of interleaved 16-bit samples.
+pub struct Analyzer {
+    settings: Settings,
+    sample_rate: u32,
+    channels: u16,
+    fft: Arc<dyn Fft<f32>>,
+    meter: Meter,
+    shown: Shown,
+    /// Consecutive full-scale samples at the end of the stream so far, per channel, so
+    /// runs spanning chunks are counted
+    clip_runs: Vec<usize>,
+}
+
+/// Levels as last drawn, for the ballistics to move on from.
+#[derive(Default)]
+struct Shown {
+    level: f32,
+    channel_levels: Vec<f32>,
+    spectrum: Vec<f32>,
+}
+
+impl Analyzer {
+    pub fn new(settings: Settings, sample_rate: u32, channels: u16) -> Self {
+        let fft_len = Self::frames_per_chunk(&settings, sample_rate)
+            .next_power_of_two()
+            .max(2);
+        let fft = FftPlanner::new().plan_fft_forward(fft_len);
+        Self {
+            settings,
+            sample_rate,
+            channels: channels.max(1),
+            fft,
+            meter: Meter::new(sample_rate, channels),
+            shown: Shown::default(),
+            clip_runs: vec![0; channels.max(1) as usize],
+        }
+    }
+
+    fn frames_per_chunk(settings: &Settings, sample_rate: u32) -> usize {
+        (sample_rate / settings.refresh_rate.max(1)).max(1) as usize
+    }
+
+    pub fn settings(&self) -> &Settings {
+        &self.settings
+    }
+
+    /// Number of interleaved samples making up one chunk.
+    pub fn chunk_size(&self) -> usize {
+        Self::frames_per_chunk(&self.settings, self.sample_rate) * self.channels as usize
+    }
+
+    /// Forgets the stream so far, for measurements that span chunks such as loudness and
+    /// the ballistics.
+    pub fn reset(&mut self) {
+        self.meter.reset();
+        self.shown = Shown::default();
+        self.clip_runs.fill(0);
+    }
+
+    /// Analyses the next chunk of interleaved samples, normally
+    /// [`chunk_size`](Self::chunk_size) long.
+    pub fn process(&mut self, chunk: &[i16]) -> Frame {
+        let channels = self.channels as usize;
+        let frames = (chunk.len() / channels).max(1) as f32;
+
+        // De-interleave into per-channel power sums and a mono mixdown for the FFT
+        let mut power = vec![0.0f32; channels];
+        let mut cross = 0.0f32;
+        let mut mono = Vec::with_capacity(chunk.len() / channels);
+        let mut peaks = vec![0u16; channels];
+        let runs = &mut self.clip_runs;
+        let mut clips = 0;
+        for frame in chunk.chunks_exact(channels) {
+            let mut sum = 0.0;
+            for (channel, &s) in frame.iter().enumerate() {
+                peaks[channel] = peaks[channel].max(s.unsigned_abs());
+                if s == i16::MAX || s == i16::MIN {
+                    runs[channel] += 1;
+                    if runs[channel] == CLIP_RUN {
+                        clips += 1;
+                    }
+                } else {
+                    runs[channel] = 0;
+                }
+
+                let s = s as f32 / i16::MAX as f32;
+                power[channel] += s * s;
+                sum += s;
+            }
+            if channels > 1 {
+                cross += frame[0] as f32 / i16::MAX as f32 * frame[1] as f32 / i16::MAX as f32;
+            }
+            mono.push(sum / channels as f32);
+        }
+
+        let rms = (power.iter().sum::<f32>() / (frames * channels as f32)).sqrt();
+        let db = 20.0 * rms.log10();
+        let channel_db: Vec<f32> = power
+            .iter()
+            .map(|&p| 20.0 * (p / frames).sqrt().log10())
+            .collect();
+        let channel_peaks: Vec<f32> = peaks
+            .iter()
+            .map(|&p| 20.0 * (p as f32 / i16::MAX as f32).log10())
+            .collect();
+        let peak = channel_peaks
+            .iter()
+            .fold(f32::NEG_INFINITY, |a, &p| a.max(p));
+        let correlation = if channels > 1 && power[0] > 0.0 && power[1] > 0.0 {
+            (cross / (power[0] * power[1]).sqrt()).clamp(-1.0, 1.0)
+        } else {
+            1.0
+        };
+
+        let spectrum: Vec<f32> = self
+            .spectrum_bands(&mono)
+            .into_iter()
+            .map(|db| self.settings.normalize(db))
+            .collect();
+        let channel_levels: Vec<f32> = channel_db
+            .iter()
+            .map(|&db| self.settings.normalize(db))
+            .collect();
+        self.meter.process(chunk);
+
+        let duration = frames / self.sample_rate as f32;
+        let ballistics = self.settings.ballistics;
+        let shown = &mut self.shown;
+        shown.level = ballistics.apply(shown.level, self.settings.normalize(db), duration);
+        ballistics.apply_all(&mut shown.channel_levels, &channel_levels, duration);
+        ballistics.apply_all(&mut shown.spectrum, &spectrum, duration);
+
+        Frame {
+            db,
+            level: shown.level,
+            peak,
+            channel_levels: shown.channel_levels.clone(),
+            channel_db,
+            channel_peak_levels: channel_peaks
+                .iter()
+                .map(|&db| self.settings.normalize(db))
+                .collect(),
+            channel_peaks,
+            clips,
+            duration,
+            loudness: self.meter.loudness(),
+            correlation,
+            spectrum: shown.spectrum.clone(),
+            waveform: mono,
+        }
+    }
+
+    /// Splits the magnitude spectrum of `frame` into log-spaced bands and returns each
+    /// band's level in dBFS. The frame is Hann windowed and zero padded to the FFT length.
+    fn spectrum_bands(&self, frame: &[f32]) -> Vec<f32> {
+        let len = self.fft.len();
+        let n = frame.len().min(len);
+        let mut buffer = vec![Complex::new(0.0, 0.0); len];
+        let mut window_sum = 0.0;
+        for (i, (&s, out)) in frame.iter().zip(&mut buffer).take(n).enumerate() {
+            let w = 0.5 - 0.5 * (2.0 * std::f32::consts::PI * i as f32 / n as f32).cos();
+            window_sum += w;
+            out.re = s * w;
+        }
+        self.fft.process(&mut buffer);
+
+        // Scale so that a full-scale sine reads 0 dBFS
+        let scale = 2.0 / window_sum.max(f32::EPSILON);
+        let magnitudes: Vec<f32> = buffer[..len / 2].iter().map(|c| c.norm() * scale).collect();
+
+        let bands = self.settings.window_size;
+        let nyquist = self.sample_rate as f32 / 2.0;
+        let bin_width = self.sample_rate as f32 / len as f32;
+        let ratio = (nyquist / MIN_FREQUENCY).max(1.0).powf(1.0 / bands as f32);
+        (0..bands)
+            .map(|band| {
+                let low = MIN_FREQUENCY * ratio.powi(band as i32);
+                let high = low * ratio;
+                let first = ((low / bin_width) as usize).min(magnitudes.len() - 1);
+                let last = ((high / bin_width) as usize).clamp(first + 1, magnitudes.len());
+                let peak = magnitudes[first..last]
+                    .iter()
+                    .fold(0.0f32, |a, &m| a.max(m));
+                20.0 * peak.log10()
+            })
+            .collect()
+    }
+}
+
+/// The most recent frames, as drawn by [`LevelChart`](crate::LevelChart).
+#[derive(Clone, Debug, Default)]
+pub struct History {
+    capacity: usize,
+    levels: Recent<f32>,
+    channel_levels: Vec<Recent<f32>>,
+    spectra: Recent<Vec<f32>>,
+    peak_holds: Vec<PeakHold>,
+    clips: usize,
+    latest: Frame,
+}
+
+/// The last `capacity` items pushed, kept contiguous. Items are only shifted once twice
+/// that many have built up, so pushing takes constant time on average.
+#[derive(Clone, Debug, Default)]
+struct Recent<T> {
+    items: Vec<T>,
+    capacity: usize,
+}
+
+impl<T> Recent<T> {
+    fn new(capacity: usize) -> Self {
+        Self {
+            items: Vec::with_capacity(capacity * 2),
+            capacity,
+        }
+    }
+
+    fn push(&mut self, item: T) {
+        if self.items.len() >= self.capacity * 2 {
+            self.items.drain(..self.items.len() - self.capacity);
+        }
+        self.items.push(item);
+    }
+
+    fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        if self.items.len() > capacity * 2 {
+            self.items.drain(..self.items.len() - capacity);
+        }
+    }
+
+    fn clear(&mut self) {
+        self.items.clear();
+    }
+
+    fn as_slice(&self) -> &[T] {
+        &self.items[self.items.len().saturating_sub(self.capacity)..]
+    }
+}
+
+/// A decaying maximum of a channel's peak level.
+#[derive(Clone, Copy, Debug, Default)]
+struct PeakHold {
+    level: f32,
+    /// Seconds since `level` was last reached
+    age: f32,
+}
+
+impl PeakHold {
+    fn update(&mut self, peak: f32, duration: f32) {
+        if peak >= self.level {
+            *self = Self {
+                level: peak,
+                age: 0.0,
+            };
+        } else {
+            self.age += duration;
+            if self.age > PEAK_HOLD_TIME {
+                self.level = (self.level - PEAK_FALL * duration).max(peak);
+            }
+        }
+    }
+}
+
+impl History {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            levels: Recent::new(capacity),
+            channel_levels: Vec::new(),
+            spectra: Recent::new(capacity),
+            peak_holds: Vec::new(),
+            clips: 0,
+            latest: Frame::default(),
+        }
+    }
+
+    pub fn push(&mut self, frame: Frame) {
+        self.levels.push(frame.level);
+        self.channel_levels
+            .resize_with(frame.channel_levels.len(), || Recent::new(self.capacity));
+        for (levels, &level) in self.channel_levels.iter_mut().zip(&frame.channel_levels) {
+            levels.push(level);
+        }
+        self.spectra.push(frame.spectrum.clone());
+        self.peak_holds
+            .resize_with(frame.channel_peak_levels.len(), PeakHold::default);
+        for (hold, &peak) in self.peak_holds.iter_mut().zip(&frame.channel_peak_levels) {
+            hold.update(peak, frame.duration);
+        }
+        self.clips += frame.clips;
+        self.latest = frame;
+    }
+
+    /// Changes how many chunks are kept, e.g. to match the width of the chart. The
+    /// oldest are dropped if there are too many.
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        self.levels.set_capacity(capacity);
+        for levels in &mut self.channel_levels {
+            levels.set_capacity(capacity);
+        }
+        self.spectra.set_capacity(capacity);
+    }
+
+    /// Forgets everything pushed so far, e.g. after a seek.
+    pub fn clear(&mut self) {
+        self.levels.clear();
+        self.channel_levels.clear();
+        self.spectra.clear();
+        self.peak_holds.clear();
+        self.clips = 0;
+        self.latest = Frame::default();
+    }
+
+    /// Normalised loudness of the last chunks, oldest first.
+    pub fn levels(&self) -> &[f32] {
+        self.levels.as_slice()
+    }
+
+    /// Normalised loudness of the last chunks of one channel, oldest first.
+    pub fn channel_levels(&self, channel: usize) -> &[f32] {
+        self.channel_levels
+            .get(channel)
+            .map_or(&[], Recent::as_slice)
+    }
+
+    /// Normalised peak-hold level of one channel: its recent maximum sample peak, held
+    /// for a moment and then falling slowly.
+    pub fn peak_hold(&self, channel: usize) -> f32 {
+        self.peak_holds.get(channel).map_or(0.0, |hold| hold.level)
+    }
+
+    /// Number of times clipping was detected since the history was created or cleared.
+    pub fn clips(&self) -> usize {
+        self.clips
+    }
+
+    /// Number of channels seen in the pushed frames.
+    pub fn channels(&self) -> usize {
+        self.channel_levels.len()
+    }
+
+    /// Normalised band levels of the latest chunk.
+    pub fn spectrum(&self) -> &[f32] {
+        &self.latest.spectrum
+    }
+
+    /// Band levels of the last chunks, oldest first, as drawn by a spectrogram.
+    pub fn spectra(&self) -> &[Vec<f32>] {
+        self.spectra.as_slice()
+    }
+
+    /// Mono samples of the latest chunk.
+    pub fn waveform(&self) -> &[f32] {
+        &self.latest.waveform
+    }
+
+    /// The most recently pushed frame.
+    pub fn latest(&self) -> &Frame {
+        &self.latest
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings(scale: Scale) -> Settings {
+        Settings {
+            scale,
+            db_floor: -60.0,
+            db_ceiling: -6.0,
+            ..Settings::default()
+        }
+    }
+
+    #[test]
+    fn scales_span_floor_to_ceiling() {
+        for scale in [Scale::Db, Scale::Perceptual] {
+            let settings = settings(scale);
+            assert_eq!(settings.normalize(-60.0), 0.0, "{scale:?}");
+            assert_eq!(settings.normalize(-6.0), 1.0, "{scale:?}");
+            assert_eq!(settings.normalize(-90.0), 0.0, "{scale:?}");
+            assert_eq!(settings.normalize(f32::NEG_INFINITY), 0.0, "{scale:?}");
+            assert_eq!(settings.normalize(0.0), 1.0, "{scale:?}");
+        }
+    }
+
+    #[test]
+    fn linear_scale_ignores_the_floor() {
+        let settings = settings(Scale::Linear);
+        assert_eq!(settings.normalize(f32::NEG_INFINITY), 0.0);
+        assert_eq!(settings.normalize(-6.0), 1.0);
+        assert_eq!(settings.normalize(0.0), 1.0);
+        // Half the ceiling's amplitude, below the floor yet still drawn
+        assert!((settings.normalize(-12.0206) - 0.5).abs() < 1e-4);
+        assert!(settings.normalize(-66.0) > 0.0);
+    }
+
+    /// Seconds for a level to get 99% of the way from `from` to `to` in 1ms steps.
+    fn settle(ballistics: Ballistics, from: f32, to: f32) -> f32 {
+        let mut shown = from;
+        let mut steps = 0;
+        while (shown - from).abs() < 0.99 * (to - from).abs() - 1e-6 {
+            shown = ballistics.apply(shown, to, 0.001);
+            steps += 1;
+        }
+        steps as f32 / 1000.0
+    }
+
+    #[test]
+    fn ballistics_settle_after_attack_and_release() {
+        for (name, attack, release) in [("vu", 0.3, 0.3), ("ppm", 0.01, 1.7)] {
+            let ballistics = Ballistics::named(name).unwrap();
+            assert!(
+                (settle(ballistics, 0.0, 1.0) - attack).abs() < 0.002,
+                "{name}"
+            );
+            assert!(
+                (settle(ballistics, 1.0, 0.0) - release).abs() < 0.002,
+                "{name}"
+            );
+        }
+        let none = Ballistics::named("none").unwrap();
+        assert_eq!(none.apply(0.0, 0.8, 0.001), 0.8);
+        assert_eq!(none.apply(0.8, 0.1, 0.001), 0.1);
+    }
+
+    #[test]
+    fn recent_keeps_the_last_items() {
+        let mut recent = Recent::new(3);
+        for i in 0..3 {
+            recent.push(i);
+        }
+        assert_eq!(recent.as_slice(), [0, 1, 2]);
+        for i in 3..20 {
+            recent.push(i);
+            assert_eq!(recent.as_slice(), [i - 2, i - 1, i]);
+            // Shifted once twice the capacity has built up, and no later
+            assert!(recent.items.len() <= 6, "{}", recent.items.len());
+        }
+        recent.clear();
+        assert!(recent.as_slice().is_empty());
+    }
+
+    #[test]
+    fn recent_resizes() {
+        let mut recent = Recent::new(4);
+        (0..8).for_each(|i| recent.push(i));
+        assert_eq!(recent.as_slice(), [4, 5, 6, 7]);
+
+        // Growing shows more of what is still held
+        recent.set_capacity(6);
+        assert_eq!(recent.as_slice(), [2, 3, 4, 5, 6, 7]);
+        // Shrinking drops what can never be shown again
+        recent.set_capacity(2);
+        assert_eq!(recent.as_slice(), [6, 7]);
+        assert_eq!(recent.items.len(), 2);
+        recent.push(8);
+        assert_eq!(recent.as_slice(), [7, 8]);
+
+        let mut empty = Recent::new(0);
+        empty.push(1);
+        assert!(empty.as_slice().is_empty());
+    }
+
+    #[test]
+    fn history_follows_its_capacity() {
+        let mut history = History::new(3);
+        for i in 0..10 {
+            history.push(Frame {
+                level: i as f32,
+                channel_levels: vec![i as f32, -(i as f32)],
+                spectrum: vec![i as f32],
+                ..Frame::default()
+            });
+        }
+        assert_eq!(history.levels(), [7.0, 8.0, 9.0]);
+        assert_eq!(history.channel_levels(1), [-7.0, -8.0, -9.0]);
+        assert_eq!(history.spectra().len(), 3);
+        history.set_capacity(2);
+        assert_eq!(history.levels(), [8.0, 9.0]);
+        assert_eq!(history.channel_levels(0), [8.0, 9.0]);
+        assert_eq!(history.spectra(), [vec![8.0], vec![9.0]]);
+    }
+
+    #[test]
+    fn ballistics_read_presets_and_times() {
+        #[derive(Deserialize)]
+        struct Table {
+            ballistics: Ballistics,
+        }
+        let read = |s: &str| toml::from_str::<Table>(s).map(|t| t.ballistics);
+        assert_eq!(
+            read("ballistics = \"ppm\"").unwrap(),
+            Ballistics::named("ppm").unwrap()
+        );
+        assert_eq!(
+            read("ballistics = { attack = 0, release = 500 }").unwrap(),
+            Ballistics {
+                attack: 0.0,
+                release: 0.5
+            }
+        );
+        let error = read("ballistics = { attack = -5 }").unwrap_err();
+        assert!(error.message().contains("non-negative"), "{error}");
+        assert!(read("ballistics = \"slow\"").is_err());
+    }
+}
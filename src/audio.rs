@@ -0,0 +1,644 @@
+use anyhow::{bail, Context, Result};
+use rodio::Source;
+use std::{
+    fs::File,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc::{self, Receiver, SyncSender},
+        Arc, Mutex,
+    },
+    thread,
+    time::Duration,
+};
+use symphonia::core::{
+    audio::SampleBuffer,
+    codecs::{self, DecoderOptions},
+    errors::Error,
+    formats::{FormatOptions, FormatReader, SeekMode, SeekTo},
+    io::MediaSourceStream,
+    meta::MetadataOptions,
+    probe::Hint,
+};
+
+const NO_SEEK: usize = usize::MAX;
+const SEEK_PREROLL: u64 = 10; // Fraction of a second decoded and dropped before a seek target
+const TEE_BATCH: usize = 1024; // Samples per buffer sent to the analysis queue, at most
+const TEE_QUEUE: usize = 64; // Buffers held before new ones are dropped
+
+struct PositionState {
+    samples: AtomicUsize,
+    /// Seek asked for but not yet picked up by the track
+    requested: AtomicUsize,
+    /// Seek being prepared, until the track jumps
+    pending: AtomicUsize,
+    seeks: AtomicUsize,
+}
+
+/// Shared view of how far a [`Track`] has got, and the handle for seeking it.
+#[derive(Clone)]
+pub struct Position(Arc<PositionState>);
+
+impl Default for Position {
+    fn default() -> Self {
+        Self(Arc::new(PositionState {
+            samples: AtomicUsize::new(0),
+            requested: AtomicUsize::new(NO_SEEK),
+            pending: AtomicUsize::new(NO_SEEK),
+            seeks: AtomicUsize::new(0),
+        }))
+    }
+}
+
+impl Position {
+    /// Number of interleaved samples handed to the output so far, i.e. the index of the
+    /// next sample to be played.
+    pub fn samples(&self) -> usize {
+        self.0.samples.load(Ordering::Acquire)
+    }
+
+    /// Asks playback to continue from the given interleaved sample index. The jump happens
+    /// once the track has decoded up to the new position, playback carries on until then.
+    pub fn seek(&self, sample: usize) {
+        self.0.requested.store(sample, Ordering::Release);
+    }
+
+    /// Where playback is heading: the latest seek target if the jump has not happened yet,
+    /// otherwise the current position. Relative seeks should start from here.
+    pub fn target(&self) -> usize {
+        match self.0.requested.load(Ordering::Acquire) {
+            NO_SEEK => match self.0.pending.load(Ordering::Acquire) {
+                NO_SEEK => self.samples(),
+                pending => pending,
+            },
+            requested => requested,
+        }
+    }
+
+    /// Number of completed seeks so far, so followers can tell a jump from normal progress.
+    pub fn seeks(&self) -> usize {
+        self.0.seeks.load(Ordering::Acquire)
+    }
+
+    fn take_request(&self) -> Option<usize> {
+        // Publish the target as pending before clearing the request, so `target` never
+        // falls back to the old position in between
+        let requested = self.0.requested.load(Ordering::Acquire);
+        if requested == NO_SEEK {
+            return None;
+        }
+        self.0.pending.store(requested, Ordering::Release);
+        let _ = self.0.requested.compare_exchange(
+            requested,
+            NO_SEEK,
+            Ordering::AcqRel,
+            Ordering::Acquire,
+        );
+        Some(requested)
+    }
+
+    fn jumped(&self, sample: usize) {
+        self.0.samples.store(sample, Ordering::Release);
+        self.0.seeks.fetch_add(1, Ordering::AcqRel);
+        self.0.pending.store(NO_SEEK, Ordering::Release);
+    }
+
+    /// Forgets a seek that could not be prepared.
+    fn abandoned(&self) {
+        self.0.pending.store(NO_SEEK, Ordering::Release);
+    }
+}
+
+/// Interleaved 16-bit samples of the default track of a file, decoded a packet at a time.
+/// Unlike rodio's decoder it can seek through the container's index, without decoding
+/// everything before the target.
+struct Stream {
+    format: Box<dyn FormatReader>,
+    decoder: Box<dyn codecs::Decoder>,
+    track_id: u32,
+    channels: u16,
+    sample_rate: u32,
+    frames: Option<u64>,
+    /// Decoded samples not handed out yet
+    samples: Vec<i16>,
+    next: usize,
+    /// Timestamp to decode up to and discard before, after a seek
+    skip_until: u64,
+    ended: bool,
+}
+
+impl Stream {
+    fn open(path: &Path) -> Result<Self> {
+        let file = File::open(path).with_context(|| format!("cannot open {}", path.display()))?;
+        let stream = MediaSourceStream::new(Box::new(file), Default::default());
+        let mut hint = Hint::new();
+        if let Some(extension) = path.extension().and_then(|e| e.to_str()) {
+            hint.with_extension(extension);
+        }
+        let unsupported = || {
+            format!(
+                "cannot decode {}: unsupported or corrupt audio",
+                path.display()
+            )
+        };
+        let format = symphonia::default::get_probe()
+            .format(
+                &hint,
+                stream,
+                &FormatOptions::default(),
+                &MetadataOptions::default(),
+            )
+            .with_context(unsupported)?
+            .format;
+        let track = format
+            .default_track()
+            .with_context(|| format!("cannot decode {}: no audio stream", path.display()))?;
+        let params = track.codec_params.clone();
+        let decoder = symphonia::default::get_codecs()
+            .make(&params, &DecoderOptions::default())
+            .with_context(unsupported)?;
+
+        let mut stream = Self {
+            track_id: track.id,
+            format,
+            decoder,
+            channels: params.channels.map_or(0, |c| c.count() as u16),
+            sample_rate: params.sample_rate.unwrap_or(0),
+            frames: params.n_frames,
+            samples: Vec::new(),
+            next: 0,
+            skip_until: 0,
+            ended: false,
+        };
+        // Some containers only say what the stream is like once it is decoded
+        if stream.channels == 0 || stream.sample_rate == 0 {
+            stream.decode();
+            if stream.channels == 0 || stream.sample_rate == 0 {
+                bail!("cannot decode {}: no audio stream", path.display());
+            }
+        }
+        Ok(stream)
+    }
+
+    /// Moves to an interleaved sample index, landing on the frame it falls in. A target
+    /// past the end ends the stream.
+    fn seek(&mut self, sample: usize) -> Result<()> {
+        if self
+            .frames
+            .is_some_and(|frames| sample as u64 >= frames * self.channels as u64)
+        {
+            self.ended = true;
+            return Ok(());
+        }
+        // Start a little early, as frames of formats like MP3 depend on the ones before
+        let frame = (sample / self.channels.max(1) as usize) as u64;
+        let preroll = self.sample_rate as u64 / SEEK_PREROLL;
+        self.format.seek(
+            SeekMode::Accurate,
+            SeekTo::TimeStamp {
+                ts: frame.saturating_sub(preroll),
+                track_id: self.track_id,
+            },
+        )?;
+        self.decoder.reset();
+        self.samples.clear();
+        self.next = 0;
+        self.skip_until = frame;
+        Ok(())
+    }
+
+    /// Decodes the next packet of the track into `samples`, returning false at the end.
+    fn decode(&mut self) -> bool {
+        loop {
+            let packet = match self.format.next_packet() {
+                Ok(packet) => packet,
+                Err(Error::IoError(err)) if err.kind() == ErrorKind::UnexpectedEof => return false,
+                Err(Error::DecodeError(_)) => continue,
+                Err(_) => return false,
+            };
+            if packet.track_id() != self.track_id {
+                continue;
+            }
+            let decoded = match self.decoder.decode(&packet) {
+                Ok(decoded) => decoded,
+                // A corrupt packet is skipped, as players do
+                Err(Error::DecodeError(_)) => continue,
+                Err(_) => return false,
+            };
+            let spec = *decoded.spec();
+            self.channels = spec.channels.count() as u16;
+            self.sample_rate = spec.rate;
+            let mut buffer = SampleBuffer::<i16>::new(decoded.capacity() as u64, spec);
+            buffer.copy_interleaved_ref(decoded);
+
+            // Drop whatever comes before the frame a seek asked for
+            let skip = self.skip_until.saturating_sub(packet.ts()) as usize;
+            let skip = (skip * self.channels as usize).min(buffer.samples().len());
+            self.samples.clear();
+            self.samples.extend_from_slice(&buffer.samples()[skip..]);
+            self.next = 0;
+            if !self.samples.is_empty() {
+                return true;
+            }
+        }
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        let frames = self.frames?;
+        (self.sample_rate > 0)
+            .then(|| Duration::from_secs_f64(frames as f64 / self.sample_rate as f64))
+    }
+}
+
+impl Iterator for Stream {
+    type Item = i16;
+
+    fn next(&mut self) -> Option<i16> {
+        if self.ended {
+            return None;
+        }
+        if self.next == self.samples.len() && !self.decode() {
+            return None;
+        }
+        let sample = self.samples[self.next];
+        self.next += 1;
+        Some(sample)
+    }
+}
+
+/// Outcome of the latest seek prepared by a seek worker, with the id of the seek: the
+/// stream at its target, or `None` if the file could not be reopened and seeked.
+type Prepared = Option<(usize, Option<(usize, Stream)>)>;
+type Replacement = Arc<Mutex<Prepared>>;
+
+/// Source that decodes a file as it plays, so memory use does not grow with its length.
+///
+/// Seeking reopens the file on a background thread and seeks it there, swapping the new
+/// stream in once it is ready so the output never waits on it. One thread serves all the
+/// seeks of a track, skipping to the newest of those that queue up while it is busy, so
+/// dragging across the progress bar does not reopen the file for every step.
+pub struct Track {
+    path: PathBuf,
+    decoder: Stream,
+    channels: u16,
+    sample_rate: u32,
+    total_duration: Option<Duration>,
+    position: Position,
+    latest_seek: Arc<AtomicUsize>,
+    replacement: Replacement,
+    /// Queue of the seek worker, once there has been a seek
+    seek_requests: Option<mpsc::Sender<(usize, usize)>>,
+    seeking: bool,
+}
+
+impl Track {
+    /// Opens and probes an audio file, failing with a readable message if it is missing
+    /// or not in a supported format.
+    pub fn open(path: &Path) -> Result<Self> {
+        let decoder = Stream::open(path)?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            channels: decoder.channels,
+            sample_rate: decoder.sample_rate,
+            total_duration: decoder.total_duration(),
+            decoder,
+            position: Position::default(),
+            latest_seek: Arc::default(),
+            replacement: Arc::default(),
+            seek_requests: None,
+            seeking: false,
+        })
+    }
+
+    pub fn position(&self) -> Position {
+        self.position.clone()
+    }
+
+    fn start_seek(&mut self, target: usize) {
+        // Land on a whole frame so the channels stay in step
+        let channels = self.channels.max(1) as usize;
+        let target = target / channels * channels;
+        self.seeking = true;
+        let id = self.latest_seek.fetch_add(1, Ordering::AcqRel) + 1;
+        let requests = self.seek_requests.get_or_insert_with(|| {
+            let (sender, requests) = mpsc::channel();
+            let latest_seek = Arc::clone(&self.latest_seek);
+            let replacement = Arc::clone(&self.replacement);
+            let path = self.path.clone();
+            thread::spawn(move || seek_worker(&path, requests, &latest_seek, &replacement));
+            sender
+        });
+        let _ = requests.send((id, target));
+    }
+}
+
+/// Prepares streams for seek requests of `(id, target)` until the track goes away. Only
+/// the newest of the requests waiting is prepared, and only kept if no other seek has
+/// started since.
+fn seek_worker(
+    path: &Path,
+    requests: Receiver<(usize, usize)>,
+    latest_seek: &AtomicUsize,
+    replacement: &Mutex<Prepared>,
+) {
+    while let Ok(mut request) = requests.recv() {
+        request = requests.try_iter().last().unwrap_or(request);
+        let (id, target) = request;
+        let prepared = Stream::open(path).and_then(|mut decoder| {
+            decoder.seek(target)?;
+            Ok((target, decoder))
+        });
+        if latest_seek.load(Ordering::Acquire) == id {
+            *replacement.lock().unwrap() = Some((id, prepared.ok()));
+        }
+    }
+}
+
+impl Iterator for Track {
+    type Item = i16;
+
+    fn next(&mut self) -> Option<i16> {
+        if let Some(target) = self.position.take_request() {
+            self.start_seek(target);
+        }
+        if self.seeking {
+            if let Ok(mut replacement) = self.replacement.try_lock() {
+                // A newer seek may have started since this one finished
+                if let Some((id, prepared)) = replacement.take() {
+                    if id == self.latest_seek.load(Ordering::Acquire) {
+                        self.seeking = false;
+                        match prepared {
+                            Some((target, decoder)) => {
+                                self.decoder = decoder;
+                                self.position.jumped(target);
+                            }
+                            // E.g. because the file went away
+                            None => self.position.abandoned(),
+                        }
+                    }
+                }
+            }
+        }
+
+        let sample = self.decoder.next();
+        match sample {
+            Some(_) => {
+                self.position.0.samples.fetch_add(1, Ordering::AcqRel);
+            }
+            // Keep the output alive with silence while a seek is still being prepared
+            None if self.seeking => return Some(0),
+            None => {}
+        }
+        sample
+    }
+}
+
+impl Source for Track {
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        self.channels
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.total_duration
+    }
+}
+
+/// Source wrapper that copies every sample the output pulls through it to a bounded
+/// queue, so an analyzer sees exactly what is being played. If the queue is full the
+/// copy is dropped rather than holding up the output.
+pub struct Tee<S> {
+    inner: S,
+    sender: SyncSender<Vec<i16>>,
+    buffer: Vec<i16>,
+    /// Samples per buffer, a whole number of frames so dropping one keeps the channels in
+    /// step
+    batch: usize,
+}
+
+impl<S: Source<Item = i16>> Tee<S> {
+    /// Wraps `inner`, returning the receiving end of the queue. It disconnects once the
+    /// tee is dropped, e.g. when the output is done with the source.
+    pub fn new(inner: S) -> (Self, Receiver<Vec<i16>>) {
+        let (sender, receiver) = mpsc::sync_channel(TEE_QUEUE);
+        let channels = inner.channels().max(1) as usize;
+        let batch = (TEE_BATCH / channels).max(1) * channels;
+        let tee = Self {
+            inner,
+            sender,
+            buffer: Vec::with_capacity(batch),
+            batch,
+        };
+        (tee, receiver)
+    }
+
+    fn flush(&mut self) {
+        if !self.buffer.is_empty() {
+            let buffer = std::mem::replace(&mut self.buffer, Vec::with_capacity(self.batch));
+            let _ = self.sender.try_send(buffer);
+        }
+    }
+}
+
+impl<S: Source<Item = i16>> Iterator for Tee<S> {
+    type Item = i16;
+
+    fn next(&mut self) -> Option<i16> {
+        let sample = self.inner.next();
+        match sample {
+            Some(s) => {
+                self.buffer.push(s);
+                if self.buffer.len() >= self.batch {
+                    self.flush();
+                }
+            }
+            None => self.flush(),
+        }
+        sample
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<S: Source<Item = i16>> Source for Tee<S> {
+    fn current_frame_len(&self) -> Option<usize> {
+        self.inner.current_frame_len()
+    }
+
+    fn channels(&self) -> u16 {
+        self.inner.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.inner.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.inner.total_duration()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{scratch_dir, write_wav};
+    use rodio::buffer::SamplesBuffer;
+    use std::{fs, time::Instant};
+
+    const RATE: u32 = 8000;
+    const FRAMES: usize = RATE as usize * 2;
+
+    /// Left counts frames up and right counts them down, so every sample says where it
+    /// came from.
+    fn sample(index: usize) -> i16 {
+        let frame = (index / 2 % 30000) as i16;
+        if index.is_multiple_of(2) {
+            frame
+        } else {
+            -frame
+        }
+    }
+
+    fn write_ramp(name: &str) -> PathBuf {
+        let path = scratch_dir(name).join("ramp.wav");
+        let samples: Vec<i16> = (0..FRAMES * 2).map(sample).collect();
+        write_wav(&path, RATE, 2, &samples);
+        path
+    }
+
+    /// Plays a track until its pending seek lands, returning the first sample after it.
+    fn until_jump(track: &mut Track, position: &Position) -> i16 {
+        let seeks = position.seeks();
+        let started = Instant::now();
+        loop {
+            let next = track.next().unwrap();
+            if position.seeks() > seeks {
+                return next;
+            }
+            assert!(
+                started.elapsed() < Duration::from_secs(5),
+                "seek never landed"
+            );
+        }
+    }
+
+    #[test]
+    fn stream_seeks_to_the_sample_asked_for() {
+        let path = write_ramp("stream-seek");
+        let mut stream = Stream::open(&path).unwrap();
+        for target in [0, 2, 4000, 12346, FRAMES * 2 - 2, 100] {
+            stream.seek(target).unwrap();
+            assert_eq!(stream.next(), Some(sample(target)), "seek to {target}");
+            assert_eq!(stream.next(), Some(sample(target + 1)), "seek to {target}");
+        }
+        stream.seek(FRAMES * 2).unwrap();
+        assert_eq!(stream.next(), None);
+    }
+
+    #[test]
+    fn track_jumps_to_a_whole_frame() {
+        let path = write_ramp("track-seek");
+        let mut track = Track::open(&path).unwrap();
+        let position = Track::position(&track);
+        track.by_ref().take(100).for_each(drop);
+
+        position.seek(9001);
+        assert_eq!(until_jump(&mut track, &position), sample(9000));
+        assert_eq!(track.next(), Some(sample(9001)));
+        assert_eq!(position.samples(), 9002);
+    }
+
+    #[test]
+    fn relative_seeks_add_up() {
+        let path = write_ramp("relative-seek");
+        let mut track = Track::open(&path).unwrap();
+        let position = Track::position(&track);
+
+        // Both before the track picks up the first, and while it is being prepared, which
+        // holding the replacement back keeps it at
+        position.seek(position.target() + 1000);
+        position.seek(position.target() + 1000);
+        assert_eq!(position.target(), 2000);
+        let replacement = Arc::clone(&track.replacement);
+        let held = replacement.lock().unwrap();
+        track.next();
+        position.seek(position.target() + 1000);
+        assert_eq!(position.target(), 3000);
+        drop(held);
+
+        assert_eq!(until_jump(&mut track, &position), sample(3000));
+        assert_eq!(position.target(), 3001);
+    }
+
+    #[test]
+    fn a_burst_of_seeks_lands_on_the_last() {
+        let path = write_ramp("burst-seek");
+        let mut track = Track::open(&path).unwrap();
+        let position = Track::position(&track);
+
+        // Like dragging across the progress bar, with playback in between, and none of the
+        // seeks landing until the drag is over
+        let replacement = Arc::clone(&track.replacement);
+        let held = replacement.lock().unwrap();
+        for step in 1..=50 {
+            position.seek(step * 200);
+            track.next();
+        }
+        assert_eq!(position.target(), 10000);
+        drop(held);
+        assert_eq!(until_jump(&mut track, &position), sample(10000));
+    }
+
+    #[test]
+    fn seeks_are_dropped_if_the_file_goes_away() {
+        let path = write_ramp("gone-seek");
+        let mut track = Track::open(&path).unwrap();
+        let position = Track::position(&track);
+        track.by_ref().take(10).for_each(drop);
+        fs::remove_file(&path).unwrap();
+
+        position.seek(5000);
+        let started = Instant::now();
+        while position.target() == 5000 {
+            track.next();
+            assert!(
+                started.elapsed() < Duration::from_secs(5),
+                "seek never gave up"
+            );
+        }
+        // Playback carries on where it was
+        assert_eq!(position.seeks(), 0);
+        let next = sample(position.samples());
+        assert_eq!(track.next(), Some(next));
+    }
+
+    #[test]
+    fn tee_sends_whole_frames() {
+        for channels in [1, 2, 3, 5, 6] {
+            let samples: Vec<i16> = (0..5000 * channels as i16).collect();
+            let source = SamplesBuffer::new(channels, RATE, samples.clone());
+            let (mut tee, receiver) = Tee::new(source);
+            let mut received = Vec::new();
+            while tee.next().is_some() {
+                received.extend(receiver.try_iter());
+            }
+            drop(tee);
+            received.extend(receiver);
+
+            for buffer in &received {
+                assert_eq!(buffer.len() % channels as usize, 0, "{channels} channels");
+            }
+            assert_eq!(received.concat(), samples, "{channels} channels");
+        }
+    }
+}
@@ -0,0 +1,191 @@
+use anyhow::{anyhow, bail, Context, Result};
+use rodio::cpal::{
+    self,
+    traits::{DeviceTrait, HostTrait, StreamTrait},
+    FromSample, SampleFormat, SizedSample, Stream, StreamConfig,
+};
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver, SyncSender},
+        Arc,
+    },
+    thread,
+    time::Duration,
+};
+
+const QUEUED_BUFFERS: usize = 64; // Captured buffers held before new ones are dropped
+
+/// Name of the built-in virtual device that delivers silence in real time, for running
+/// the capture pipeline without any audio hardware.
+pub const NULL_DEVICE: &str = "null";
+const NULL_SAMPLE_RATE: u32 = 48_000;
+const NULL_CHANNELS: u16 = 2;
+const NULL_PERIOD: Duration = Duration::from_millis(10);
+
+/// Names of the input devices on the default host, followed by [`NULL_DEVICE`].
+pub fn input_devices() -> Result<Vec<String>> {
+    let host = cpal::default_host();
+    Ok(host
+        .input_devices()
+        .context("cannot list input devices")?
+        .filter_map(|d| d.name().ok())
+        .chain(std::iter::once(NULL_DEVICE.to_string()))
+        .collect())
+}
+
+enum Input {
+    Device { _stream: Stream },
+    Null(Arc<AtomicBool>),
+}
+
+/// Running capture from an input device such as a microphone or line-in.
+pub struct Capture {
+    input: Input,
+    name: String,
+    sample_rate: u32,
+    channels: u16,
+}
+
+impl Capture {
+    /// Starts capturing from the named input device, or the default one. The receiver
+    /// yields interleaved buffers as the device delivers them, until the capture is dropped.
+    /// If the receiver falls behind, new buffers are dropped rather than blocking the device.
+    pub fn open(device: Option<&str>) -> Result<(Self, Receiver<Vec<i16>>)> {
+        if device == Some(NULL_DEVICE) {
+            return Ok(Self::null());
+        }
+
+        let host = cpal::default_host();
+        let device = match device {
+            Some(name) => host
+                .input_devices()
+                .context("cannot list input devices")?
+                .find(|d| d.name().is_ok_and(|n| n == name))
+                .ok_or_else(|| anyhow!("no input device named `{name}`"))?,
+            None => host
+                .default_input_device()
+                .ok_or_else(|| anyhow!("no default input device"))?,
+        };
+        let name = device.name().unwrap_or_else(|_| "unknown".to_string());
+        let supported = device
+            .default_input_config()
+            .with_context(|| format!("cannot configure input device `{name}`"))?;
+        let config: StreamConfig = supported.config();
+
+        let (sender, receiver) = mpsc::sync_channel(QUEUED_BUFFERS);
+        let stream = match supported.sample_format() {
+            SampleFormat::I16 => build::<i16>(&device, &config, sender),
+            SampleFormat::U16 => build::<u16>(&device, &config, sender),
+            SampleFormat::I32 => build::<i32>(&device, &config, sender),
+            SampleFormat::F32 => build::<f32>(&device, &config, sender),
+            format => bail!("input device `{name}` uses unsupported sample format {format}"),
+        }
+        .with_context(|| format!("cannot open input device `{name}`"))?;
+        stream
+            .play()
+            .with_context(|| format!("cannot start input device `{name}`"))?;
+
+        let capture = Self {
+            input: Input::Device { _stream: stream },
+            name,
+            sample_rate: config.sample_rate.0,
+            channels: config.channels,
+        };
+        Ok((capture, receiver))
+    }
+
+    fn null() -> (Self, Receiver<Vec<i16>>) {
+        let (sender, receiver) = mpsc::sync_channel(QUEUED_BUFFERS);
+        let stopped = Arc::new(AtomicBool::new(false));
+        let stopped_clone = Arc::clone(&stopped);
+        thread::spawn(move || {
+            let len = (NULL_SAMPLE_RATE as u128 * NULL_PERIOD.as_millis() / 1000) as usize
+                * NULL_CHANNELS as usize;
+            while !stopped_clone.load(Ordering::Acquire) {
+                if let Err(mpsc::TrySendError::Disconnected(_)) = sender.try_send(vec![0; len]) {
+                    break;
+                }
+                thread::sleep(NULL_PERIOD);
+            }
+        });
+
+        let capture = Self {
+            input: Input::Null(stopped),
+            name: NULL_DEVICE.to_string(),
+            sample_rate: NULL_SAMPLE_RATE,
+            channels: NULL_CHANNELS,
+        };
+        (capture, receiver)
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    pub fn channels(&self) -> u16 {
+        self.channels
+    }
+}
+
+impl Drop for Capture {
+    fn drop(&mut self) {
+        if let Input::Null(stopped) = &self.input {
+            stopped.store(true, Ordering::Release);
+        }
+    }
+}
+
+fn build<T>(
+    device: &cpal::Device,
+    config: &StreamConfig,
+    sender: SyncSender<Vec<i16>>,
+) -> Result<Stream>
+where
+    T: SizedSample,
+    i16: FromSample<T>,
+{
+    let stream = device.build_input_stream(
+        config,
+        move |data: &[T], _| {
+            let buffer = data.iter().map(|s| s.to_sample::<i16>()).collect();
+            let _ = sender.try_send(buffer);
+        },
+        |_| {},
+        None,
+    )?;
+    Ok(stream)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc::RecvTimeoutError;
+
+    #[test]
+    fn null_device_delivers_silence_until_dropped() {
+        let (capture, receiver) = Capture::open(Some(NULL_DEVICE)).unwrap();
+        assert_eq!(capture.name(), NULL_DEVICE);
+        assert_eq!(capture.sample_rate(), NULL_SAMPLE_RATE);
+        assert_eq!(capture.channels(), NULL_CHANNELS);
+
+        let buffer = receiver.recv_timeout(Duration::from_secs(1)).unwrap();
+        // One period of interleaved frames
+        assert_eq!(buffer.len(), 480 * 2);
+        assert!(buffer.iter().all(|&s| s == 0));
+
+        drop(capture);
+        // Whatever was queued drains, then the stream ends
+        loop {
+            match receiver.recv_timeout(Duration::from_secs(1)) {
+                Ok(_) => continue,
+                Err(RecvTimeoutError::Disconnected) => break,
+                Err(RecvTimeoutError::Timeout) => panic!("stream still open after drop"),
+            }
+        }
+    }
+}
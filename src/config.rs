@@ -0,0 +1,870 @@
+//! TOML configuration, read from `$XDG_CONFIG_HOME/audio-vis/config.toml` (or the
+//! platform equivalent) unless another file is given. Every key is optional:
+//!
+//! ```toml
+//! title = "Audio Visualization"
+//! theme = "default"   # default, classic, high-contrast or mono
+//!
+//! [analysis]
+//! window_size = 100   # bars to aim for, and spectrum bands
+//! refresh_rate = 20   # chunks per second, i.e. 50ms chunks
+//! scale = "db"        # linear, db or perceptual
+//! db_floor = -60.0    # dB drawn as an empty bar
+//! db_ceiling = 0.0    # dB drawn as a full bar
+//! ballistics = "none" # none, vu, ppm or { attack = 10, release = 1500 } in ms
+//!
+//! [layout]
+//! min_bar_width = 1   # cells; fewer bars are shown if they would be narrower
+//! max_bar_width = 4   # more bars are shown if they would be wider
+//!
+//! [colors]           # overrides for colours of the theme
+//! bars = ["green", "yellow", "red"]  # one colour, or a gradient from empty to full
+//! values = "black"    # a name, "#rrggbb" or a 0-255 palette index
+//! border = "reset"
+//!
+//! [keys]
+//! quit = ["q"]
+//! next_mode = ["tab"]
+//! next_view = ["v"]
+//! pause = ["space"]
+//! seek_back = ["left"]
+//! seek_forward = ["right"]
+//! volume_up = ["+", "="]
+//! volume_down = ["-"]
+//! restart = ["r"]
+//! next_track = ["n"]
+//! previous_track = ["p"]
+//!
+//! [[views]]           # split screens for next_view to cycle through after the single chart
+//! name = "studio"
+//! columns = [         # or rows; a pane is a mode, or a table of mode, rows or columns
+//!     { rows = ["spectrum", { columns = ["scope", "levels"] }] },
+//!     { mode = "meter", size = 25 },  # percent of the split, the rest share what is left
+//! ]
+//! ```
+
+use crate::{
+    theme::{ColorSupport, Gradient},
+    Registry, Settings, Theme,
+};
+use anyhow::{bail, Context, Result};
+use ratatui::{layout::Direction, style::Color};
+use serde::{
+    de::{self, value::MapAccessDeserializer, MapAccess, SeqAccess, Visitor},
+    Deserialize, Deserializer,
+};
+use std::{
+    fmt, fs,
+    path::{Path, PathBuf},
+};
+
+/// Everything that can be set in the configuration file.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Text at the start of the chart title
+    pub title: String,
+    /// Name of a built-in [`Theme`]
+    pub theme: String,
+    pub analysis: Settings,
+    pub layout: Layout,
+    pub colors: Colors,
+    pub keys: Keys,
+    pub views: Vec<View>,
+    /// File the configuration was loaded from, if any
+    #[serde(skip)]
+    path: Option<PathBuf>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            title: "Audio Visualization".to_string(),
+            theme: "default".to_string(),
+            analysis: Settings::default(),
+            layout: Layout::default(),
+            colors: Colors::default(),
+            keys: Keys::default(),
+            views: Vec::new(),
+            path: None,
+        }
+    }
+}
+
+impl Config {
+    /// Location of the configuration file when none is given explicitly.
+    pub fn default_path() -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join("audio-vis").join("config.toml"))
+    }
+
+    /// Loads the given file, or the default one if it exists, falling back to the
+    /// built-in defaults. Errors name the file and the offending key.
+    pub fn load(path: Option<&Path>) -> Result<Self> {
+        let path = match path {
+            Some(path) => path.to_path_buf(),
+            None => match Self::default_path() {
+                Some(path) if path.exists() => path,
+                _ => return Ok(Self::default()),
+            },
+        };
+
+        let text = fs::read_to_string(&path)
+            .with_context(|| format!("cannot read config {}", path.display()))?;
+        let mut config: Self =
+            toml::from_str(&text).with_context(|| format!("invalid config {}", path.display()))?;
+        config
+            .validate()
+            .with_context(|| format!("invalid config {}", path.display()))?;
+        config.path = Some(path);
+        Ok(config)
+    }
+
+    /// The chosen theme with the colour overrides applied, limited to what the terminal
+    /// can show.
+    pub fn theme(&self, support: ColorSupport) -> Theme {
+        let mut theme = Theme::named(&self.theme).unwrap_or_default();
+        let colors = &self.colors;
+        if let Some(bars) = &colors.bars {
+            theme.bars = bars.clone();
+        }
+        if let Some(values) = colors.values {
+            theme.values = values;
+        }
+        if let Some(border) = colors.border {
+            theme.border = border;
+        }
+        theme.support(support)
+    }
+
+    fn validate(&self) -> Result<()> {
+        if Theme::named(&self.theme).is_none() {
+            bail!(
+                "theme: unknown theme `{}`, expected default, classic, high-contrast or mono",
+                self.theme
+            );
+        }
+        let analysis = &self.analysis;
+        if analysis.window_size == 0 {
+            bail!("analysis.window_size: must be at least 1");
+        }
+        if !(1..=1000).contains(&analysis.refresh_rate) {
+            bail!("analysis.refresh_rate: must be between 1 and 1000");
+        }
+        if !analysis.db_floor.is_finite() || analysis.db_floor >= 0.0 {
+            bail!("analysis.db_floor: must be a negative number of dB");
+        }
+        if !analysis.db_ceiling.is_finite() || analysis.db_ceiling <= analysis.db_floor {
+            bail!("analysis.db_ceiling: must be a number of dB above analysis.db_floor");
+        }
+        if self.layout.min_bar_width == 0 {
+            bail!("layout.min_bar_width: must be at least 1");
+        }
+        if self.layout.max_bar_width < self.layout.min_bar_width {
+            bail!("layout.max_bar_width: must be at least layout.min_bar_width");
+        }
+        for (i, view) in self.views.iter().enumerate() {
+            if self.views[..i].iter().any(|v| v.name == view.name) {
+                bail!("views: `{}` is defined twice", view.name);
+            }
+        }
+        self.keys.validate()
+    }
+
+    /// Checks that the views only use visualisers that are registered.
+    pub fn check_views(&self, visualizers: &Registry) -> Result<()> {
+        self.check_view_modes(visualizers)
+            .with_context(|| match &self.path {
+                Some(path) => format!("invalid config {}", path.display()),
+                None => "invalid config".to_string(),
+            })
+    }
+
+    fn check_view_modes(&self, visualizers: &Registry) -> Result<()> {
+        for view in &self.views {
+            for name in view.pane.visualizers() {
+                if visualizers.get(name).is_none() {
+                    let names: Vec<String> = visualizers.names().map(str::to_lowercase).collect();
+                    bail!(
+                        "views.{}: unknown mode `{name}`, expected one of {}",
+                        view.name,
+                        names.join(", ")
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Sizing of the bars, which otherwise adapt to the terminal.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Layout {
+    pub min_bar_width: u16,
+    pub max_bar_width: u16,
+}
+
+impl Default for Layout {
+    fn default() -> Self {
+        Self {
+            min_bar_width: 1,
+            max_bar_width: 4,
+        }
+    }
+}
+
+/// Colours of the chart that replace those of the theme.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Colors {
+    #[serde(deserialize_with = "gradient")]
+    pub bars: Option<Gradient>,
+    /// Value labels drawn on top of full bars
+    #[serde(deserialize_with = "color")]
+    pub values: Option<Color>,
+    #[serde(deserialize_with = "color")]
+    pub border: Option<Color>,
+}
+
+/// Parses a colour name, `#rrggbb` or palette index.
+pub fn parse_color(s: &str) -> Option<Color> {
+    let color = match s.to_ascii_lowercase().replace(['-', '_', ' '], "").as_str() {
+        "reset" | "default" => Color::Reset,
+        "black" => Color::Black,
+        "red" => Color::Red,
+        "green" => Color::Green,
+        "yellow" => Color::Yellow,
+        "blue" => Color::Blue,
+        "magenta" => Color::Magenta,
+        "cyan" => Color::Cyan,
+        "gray" | "grey" => Color::Gray,
+        "darkgray" | "darkgrey" => Color::DarkGray,
+        "lightred" => Color::LightRed,
+        "lightgreen" => Color::LightGreen,
+        "lightyellow" => Color::LightYellow,
+        "lightblue" => Color::LightBlue,
+        "lightmagenta" => Color::LightMagenta,
+        "lightcyan" => Color::LightCyan,
+        "white" => Color::White,
+        // Separators are only skipped in names
+        _ if s.starts_with('#') && s.len() == 7 => {
+            let rgb = u32::from_str_radix(&s[1..], 16).ok()?;
+            Color::Rgb((rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8)
+        }
+        _ => Color::Indexed(s.parse().ok()?),
+    };
+    Some(color)
+}
+
+fn unknown_color<E: de::Error>(s: &str) -> E {
+    E::custom(format!(
+        "unknown colour `{s}`, expected a name like \"yellow\", \"#rrggbb\" or 0-255"
+    ))
+}
+
+fn color<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Color>, D::Error> {
+    let s = String::deserialize(deserializer)?;
+    parse_color(&s).map(Some).ok_or_else(|| unknown_color(&s))
+}
+
+/// A single colour or a list of them.
+fn gradient<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Gradient>, D::Error> {
+    struct Colors;
+
+    impl<'de> Visitor<'de> for Colors {
+        type Value = Vec<String>;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "a colour or a list of colours")
+        }
+
+        fn visit_str<E: de::Error>(self, s: &str) -> Result<Self::Value, E> {
+            Ok(vec![s.to_string()])
+        }
+
+        fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+            let mut colors = Vec::new();
+            while let Some(color) = seq.next_element()? {
+                colors.push(color);
+            }
+            Ok(colors)
+        }
+    }
+
+    let names = deserializer.deserialize_any(Colors)?;
+    if names.is_empty() {
+        return Err(de::Error::custom("expected at least one colour"));
+    }
+    let colors = names
+        .iter()
+        .map(|s| parse_color(s).ok_or_else(|| unknown_color(s)))
+        .collect::<Result<_, _>>()?;
+    Ok(Some(Gradient::new(colors)))
+}
+
+/// A screen split into panes, each showing a visualiser.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "ViewTable")]
+pub struct View {
+    pub name: String,
+    pub pane: Pane,
+}
+
+/// A view as written in the file: a name and what a pane would have, without a size.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ViewTable {
+    name: String,
+    mode: Option<String>,
+    rows: Option<Vec<Pane>>,
+    columns: Option<Vec<Pane>>,
+}
+
+impl TryFrom<ViewTable> for View {
+    type Error = de::value::Error;
+
+    fn try_from(table: ViewTable) -> Result<Self, Self::Error> {
+        let pane = PaneTable {
+            mode: table.mode,
+            rows: table.rows,
+            columns: table.columns,
+            size: None,
+        }
+        .pane()?;
+        Ok(View {
+            name: table.name,
+            pane,
+        })
+    }
+}
+
+/// Part of a [`View`], sized as a percentage of the split it is in. Panes without a size
+/// share what the others leave equally.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Pane {
+    pub kind: PaneKind,
+    pub size: Option<u16>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PaneKind {
+    /// A visualiser, by name
+    Visualizer(String),
+    /// Panes stacked (vertical) or side by side (horizontal)
+    Split(Direction, Vec<Pane>),
+}
+
+impl Pane {
+    /// Names of the visualisers in this pane and all those inside it.
+    pub fn visualizers(&self) -> Vec<&str> {
+        match &self.kind {
+            PaneKind::Visualizer(name) => vec![name],
+            PaneKind::Split(_, panes) => panes.iter().flat_map(Pane::visualizers).collect(),
+        }
+    }
+}
+
+/// A pane as written in the file, before checking that it is exactly one thing.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PaneTable {
+    mode: Option<String>,
+    rows: Option<Vec<Pane>>,
+    columns: Option<Vec<Pane>>,
+    size: Option<u16>,
+}
+
+impl PaneTable {
+    fn pane<E: de::Error>(self) -> Result<Pane, E> {
+        let kind = match (self.mode, self.rows, self.columns) {
+            (Some(mode), None, None) => PaneKind::Visualizer(mode),
+            (None, Some(panes), None) => PaneKind::Split(Direction::Vertical, panes),
+            (None, None, Some(panes)) => PaneKind::Split(Direction::Horizontal, panes),
+            _ => return Err(E::custom("expected exactly one of mode, rows or columns")),
+        };
+        if let PaneKind::Split(_, panes) = &kind {
+            if panes.is_empty() {
+                return Err(E::custom("expected at least one pane"));
+            }
+            if panes.iter().filter_map(|p| p.size).sum::<u16>() > 100 {
+                return Err(E::custom("pane sizes add up to more than 100"));
+            }
+        }
+        if self.size.is_some_and(|size| !(1..=100).contains(&size)) {
+            return Err(E::custom("size: must be a percentage between 1 and 100"));
+        }
+        Ok(Pane {
+            kind,
+            size: self.size,
+        })
+    }
+}
+
+/// A mode name, or a table of mode, rows or columns with an optional size.
+impl<'de> Deserialize<'de> for Pane {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct Panes;
+
+        impl<'de> Visitor<'de> for Panes {
+            type Value = Pane;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "a mode or a table of mode, rows or columns")
+            }
+
+            fn visit_str<E: de::Error>(self, s: &str) -> Result<Pane, E> {
+                Ok(Pane {
+                    kind: PaneKind::Visualizer(s.to_string()),
+                    size: None,
+                })
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<Pane, A::Error> {
+                PaneTable::deserialize(MapAccessDeserializer::new(map))?.pane()
+            }
+        }
+
+        deserializer.deserialize_any(Panes)
+    }
+}
+
+/// A key that can be bound to a [`Command`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Key {
+    Char(char),
+    Tab,
+    Enter,
+    Esc,
+    Backspace,
+    Left,
+    Right,
+    Up,
+    Down,
+    Home,
+    End,
+    PageUp,
+    PageDown,
+}
+
+impl TryFrom<String> for Key {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, String> {
+        let mut chars = s.chars();
+        if let (Some(c), None) = (chars.next(), chars.next()) {
+            return Ok(Key::Char(c));
+        }
+        let key = match s.to_ascii_lowercase().as_str() {
+            "space" => Key::Char(' '),
+            "tab" => Key::Tab,
+            "enter" | "return" => Key::Enter,
+            "esc" | "escape" => Key::Esc,
+            "backspace" => Key::Backspace,
+            "left" => Key::Left,
+            "right" => Key::Right,
+            "up" => Key::Up,
+            "down" => Key::Down,
+            "home" => Key::Home,
+            "end" => Key::End,
+            "pageup" => Key::PageUp,
+            "pagedown" => Key::PageDown,
+            _ => {
+                return Err(format!(
+                    "unknown key `{s}`, expected a single character or a name like \"space\" or \"left\""
+                ))
+            }
+        };
+        Ok(key)
+    }
+}
+
+impl fmt::Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Key::Char(' ') => write!(f, "space"),
+            Key::Char(c) => write!(f, "{c}"),
+            other => write!(f, "{}", format!("{other:?}").to_lowercase()),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Key {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Key::try_from(String::deserialize(deserializer)?).map_err(de::Error::custom)
+    }
+}
+
+/// Something the user can ask for from the keyboard.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Command {
+    Quit,
+    NextMode,
+    NextView,
+    Pause,
+    SeekBack,
+    SeekForward,
+    VolumeUp,
+    VolumeDown,
+    Restart,
+    NextTrack,
+    PreviousTrack,
+}
+
+/// Keys bound to each [`Command`].
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Keys {
+    pub quit: Vec<Key>,
+    pub next_mode: Vec<Key>,
+    pub next_view: Vec<Key>,
+    pub pause: Vec<Key>,
+    pub seek_back: Vec<Key>,
+    pub seek_forward: Vec<Key>,
+    pub volume_up: Vec<Key>,
+    pub volume_down: Vec<Key>,
+    pub restart: Vec<Key>,
+    pub next_track: Vec<Key>,
+    pub previous_track: Vec<Key>,
+}
+
+impl Default for Keys {
+    fn default() -> Self {
+        Self {
+            quit: vec![Key::Char('q')],
+            next_mode: vec![Key::Tab],
+            next_view: vec![Key::Char('v')],
+            pause: vec![Key::Char(' ')],
+            seek_back: vec![Key::Left],
+            seek_forward: vec![Key::Right],
+            volume_up: vec![Key::Char('+'), Key::Char('=')],
+            volume_down: vec![Key::Char('-')],
+            restart: vec![Key::Char('r')],
+            next_track: vec![Key::Char('n')],
+            previous_track: vec![Key::Char('p')],
+        }
+    }
+}
+
+impl Keys {
+    fn bindings(&self) -> [(&'static str, Command, &[Key]); 11] {
+        [
+            ("quit", Command::Quit, &self.quit),
+            ("next_mode", Command::NextMode, &self.next_mode),
+            ("next_view", Command::NextView, &self.next_view),
+            ("pause", Command::Pause, &self.pause),
+            ("seek_back", Command::SeekBack, &self.seek_back),
+            ("seek_forward", Command::SeekForward, &self.seek_forward),
+            ("volume_up", Command::VolumeUp, &self.volume_up),
+            ("volume_down", Command::VolumeDown, &self.volume_down),
+            ("restart", Command::Restart, &self.restart),
+            ("next_track", Command::NextTrack, &self.next_track),
+            (
+                "previous_track",
+                Command::PreviousTrack,
+                &self.previous_track,
+            ),
+        ]
+    }
+
+    /// The command bound to `key`, if any.
+    pub fn command(&self, key: Key) -> Option<Command> {
+        self.bindings()
+            .into_iter()
+            .find(|(_, _, keys)| keys.contains(&key))
+            .map(|(_, command, _)| command)
+    }
+
+    fn validate(&self) -> Result<()> {
+        let bindings = self.bindings();
+        for (i, (name, _, keys)) in bindings.iter().enumerate() {
+            for key in keys.iter() {
+                if let Some((other, _, _)) = bindings[..i].iter().find(|(_, _, k)| k.contains(key))
+                {
+                    bail!("keys.{name}: `{key}` is already bound to keys.{other}");
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(text: &str) -> Result<Config> {
+        let config: Config = toml::from_str(text)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// The error for a config, which must name `key`.
+    fn error(text: &str, key: &str) -> String {
+        let message = format!("{:#}", parse(text).unwrap_err());
+        assert!(message.contains(key), "`{key}` not named in: {message}");
+        message
+    }
+
+    #[test]
+    fn empty_config_is_the_default() {
+        let config = parse("").unwrap();
+        assert_eq!(config.title, "Audio Visualization");
+        assert_eq!(config.analysis.db_floor, -60.0);
+        assert_eq!(config.keys.command(Key::Char('q')), Some(Command::Quit));
+    }
+
+    #[test]
+    fn reads_every_section() {
+        let config = parse(
+            r##"
+            title = "Studio"
+            theme = "classic"
+
+            [analysis]
+            window_size = 64
+            refresh_rate = 30
+            scale = "perceptual"
+            db_floor = -48.0
+            db_ceiling = -6.0
+            ballistics = "vu"
+
+            [layout]
+            min_bar_width = 2
+            max_bar_width = 2
+
+            [colors]
+            bars = ["dark-gray", "#ff8000", "202"]
+            values = "White"
+            border = "reset"
+
+            [keys]
+            quit = ["esc", "Q"]
+            pause = ["p"]
+            previous_track = ["b"]
+            "##,
+        )
+        .unwrap();
+        assert_eq!(config.title, "Studio");
+        assert_eq!(config.analysis.window_size, 64);
+        assert_eq!(config.analysis.scale, crate::Scale::Perceptual);
+        assert_eq!(config.analysis.db_ceiling, -6.0);
+        assert_eq!(config.layout.min_bar_width, 2);
+        assert_eq!(
+            config.colors.bars,
+            Some(Gradient::new(vec![
+                Color::DarkGray,
+                Color::Rgb(255, 128, 0),
+                Color::Indexed(202)
+            ]))
+        );
+        assert_eq!(config.colors.values, Some(Color::White));
+        assert_eq!(config.colors.border, Some(Color::Reset));
+        assert_eq!(config.keys.command(Key::Esc), Some(Command::Quit));
+        assert_eq!(config.keys.command(Key::Char('p')), Some(Command::Pause));
+        assert_eq!(
+            config.keys.command(Key::Char('b')),
+            Some(Command::PreviousTrack)
+        );
+        // Single colours are a gradient of one
+        let config = parse("[colors]\nbars = \"yellow\"").unwrap();
+        assert_eq!(config.colors.bars, Some(Gradient::solid(Color::Yellow)));
+    }
+
+    #[test]
+    fn errors_name_the_key() {
+        error("theme = \"neon\"", "theme: unknown theme `neon`");
+        error("tittle = \"x\"", "tittle");
+        error("[analysis]\nwindow_size = 0", "analysis.window_size");
+        error("[analysis]\nrefresh_rate = 2000", "analysis.refresh_rate");
+        error("[analysis]\ndb_floor = 3.0", "analysis.db_floor");
+        error(
+            "[analysis]\ndb_floor = -40.0\ndb_ceiling = -50.0",
+            "analysis.db_ceiling",
+        );
+        error("[analysis]\nscale = \"loud\"", "scale");
+        error("[layout]\nmin_bar_width = 0", "layout.min_bar_width");
+        error(
+            "[layout]\nmin_bar_width = 3\nmax_bar_width = 2",
+            "layout.max_bar_width",
+        );
+        error("[layout]\nwidth = 3", "width");
+        let message = error("[colors]\nbars = [\"green\", \"mauve\"]", "bars");
+        assert!(message.contains("unknown colour `mauve`"), "{message}");
+        let message = error("[colors]\nbars = []", "bars");
+        assert!(message.contains("at least one colour"), "{message}");
+        error("[colors]\nvalues = \"#12345\"", "values");
+        error("[colors]\nborder = \"256\"", "border");
+        let message = error("[keys]\nquit = [\"ctrl-q\"]", "quit");
+        assert!(message.contains("unknown key `ctrl-q`"), "{message}");
+        error("[keys]\nrestart = \"r\"", "restart");
+    }
+
+    #[test]
+    fn keys_are_bound_once() {
+        error(
+            "[keys]\nquit = [\"n\"]",
+            "keys.next_track: `n` is already bound to keys.quit",
+        );
+        error(
+            "[keys]\npause = [\"x\"]\nrestart = [\"x\", \"y\"]",
+            "keys.restart: `x` is already bound to keys.pause",
+        );
+        // Moving a key off its default binding frees it
+        parse("[keys]\nquit = [\"n\"]\nnext_track = [\"j\"]").unwrap();
+    }
+
+    fn mode(name: &str) -> Pane {
+        Pane {
+            kind: PaneKind::Visualizer(name.to_string()),
+            size: None,
+        }
+    }
+
+    #[test]
+    fn documented_example_parses() {
+        let source = include_str!("config.rs");
+        let example: String = source
+            .lines()
+            .filter_map(|line| line.strip_prefix("//!"))
+            .skip_while(|line| line.trim() != "```toml")
+            .skip(1)
+            .take_while(|line| line.trim() != "```")
+            .map(|line| format!("{}\n", line.strip_prefix(' ').unwrap_or(line)))
+            .collect();
+        let config = parse(&example).unwrap();
+        assert_eq!(config.views.len(), 1);
+        config.check_views(&Registry::default()).unwrap();
+    }
+
+    #[test]
+    fn views_nest_rows_and_columns() {
+        let config = parse(
+            r#"
+            [[views]]
+            name = "studio"
+            columns = [
+                { rows = ["spectrum", { columns = ["scope", "levels"], size = 40 }] },
+                { mode = "meter", size = 25 },
+            ]
+
+            [[views]]
+            name = "single"
+            mode = "spectrogram"
+            "#,
+        )
+        .unwrap();
+        let studio = &config.views[0];
+        assert_eq!(studio.name, "studio");
+        let inner = Pane {
+            kind: PaneKind::Split(Direction::Horizontal, vec![mode("scope"), mode("levels")]),
+            size: Some(40),
+        };
+        let left = Pane {
+            kind: PaneKind::Split(Direction::Vertical, vec![mode("spectrum"), inner]),
+            size: None,
+        };
+        let right = Pane {
+            size: Some(25),
+            ..mode("meter")
+        };
+        assert_eq!(
+            studio.pane,
+            Pane {
+                kind: PaneKind::Split(Direction::Horizontal, vec![left, right]),
+                size: None,
+            }
+        );
+        assert_eq!(
+            studio.pane.visualizers(),
+            ["spectrum", "scope", "levels", "meter"]
+        );
+        assert_eq!(config.views[1].pane, mode("spectrogram"));
+    }
+
+    #[test]
+    fn views_reject_bad_splits() {
+        let view = |body: &str| format!("[[views]]\nname = \"v\"\n{body}");
+        error(
+            &view("rows = [{ mode = \"levels\", size = 60 }, { mode = \"scope\", size = 50 }]"),
+            "pane sizes add up to more than 100",
+        );
+        error(&view("columns = []"), "expected at least one pane");
+        error(
+            &view("rows = [{ columns = [] }]"),
+            "expected at least one pane",
+        );
+        error(
+            &view("mode = \"levels\"\nrows = [\"scope\"]"),
+            "expected exactly one of mode, rows or columns",
+        );
+        error(
+            &view("rows = [{ mode = \"levels\", columns = [\"scope\"] }]"),
+            "expected exactly one of mode, rows or columns",
+        );
+        error(&view(""), "expected exactly one of mode, rows or columns");
+        error(
+            &view("rows = [{ mode = \"levels\", size = 0 }]"),
+            "size: must be a percentage between 1 and 100",
+        );
+        error(&view("mode = \"levels\"\nsize = 50"), "size");
+        error(&view("rows = [{ mod = \"levels\" }]"), "mod");
+        error(
+            &view("rows = [3]"),
+            "a mode or a table of mode, rows or columns",
+        );
+        error(
+            "[[views]]\nname = \"v\"\nmode = \"levels\"\n[[views]]\nname = \"v\"\nmode = \"scope\"",
+            "views: `v` is defined twice",
+        );
+    }
+
+    #[test]
+    fn unknown_view_modes_name_the_file() {
+        let dir = crate::testing::scratch_dir("config-views");
+        let path = dir.join("config.toml");
+        fs::write(
+            &path,
+            "[[views]]\nname = \"studio\"\nrows = [\"levels\", \"sparkles\"]",
+        )
+        .unwrap();
+        let config = Config::load(Some(&path)).unwrap();
+        let message = format!(
+            "{:#}",
+            config.check_views(&Registry::default()).unwrap_err()
+        );
+        assert!(message.contains(&path.display().to_string()), "{message}");
+        assert!(
+            message.contains("views.studio: unknown mode `sparkles`"),
+            "{message}"
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn colours_parse_by_name_hex_or_index() {
+        assert_eq!(parse_color("Light_Blue"), Some(Color::LightBlue));
+        assert_eq!(parse_color("dark grey"), Some(Color::DarkGray));
+        assert_eq!(parse_color("default"), Some(Color::Reset));
+        assert_eq!(parse_color("#0A0b0C"), Some(Color::Rgb(10, 11, 12)));
+        assert_eq!(parse_color("0"), Some(Color::Indexed(0)));
+        assert_eq!(parse_color("255"), Some(Color::Indexed(255)));
+        for bad in ["mauve", "#12345", "#1234567", "#gggggg", "256", "-1", ""] {
+            assert_eq!(parse_color(bad), None, "{bad}");
+        }
+    }
+
+    #[test]
+    fn keys_parse_by_character_or_name() {
+        let key = |s: &str| Key::try_from(s.to_string());
+        assert_eq!(key("a"), Ok(Key::Char('a')));
+        assert_eq!(key("Q"), Ok(Key::Char('Q')));
+        assert_eq!(key("é"), Ok(Key::Char('é')));
+        assert_eq!(key("space"), Ok(Key::Char(' ')));
+        assert_eq!(key("Return"), Ok(Key::Enter));
+        assert_eq!(key("PageUp"), Ok(Key::PageUp));
+        assert!(key("ctrl").unwrap_err().contains("unknown key `ctrl`"));
+        assert!(key("").is_err());
+        assert_eq!(Key::Char(' ').to_string(), "space");
+        assert_eq!(Key::PageDown.to_string(), "pagedown");
+    }
+}
@@ -0,0 +1,306 @@
+//! Headless analysis of whole files, written out chunk by chunk for other tools.
+
+use crate::{audio::Track, Analyzer, Frame, Settings};
+use anyhow::{bail, Context, Result};
+use rodio::Source;
+use serde::Serialize;
+use std::{io::Write, path::Path, str::FromStr};
+
+/// Output format of an [`Exporter`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    /// Comma separated values with a header row
+    Csv,
+    /// One JSON object per line
+    JsonLines,
+}
+
+impl Format {
+    /// Guesses the format from a file extension, defaulting to CSV.
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("json" | "jsonl" | "ndjson") => Format::JsonLines,
+            _ => Format::Csv,
+        }
+    }
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "csv" => Ok(Format::Csv),
+            "jsonl" | "json" => Ok(Format::JsonLines),
+            _ => Err(format!("unknown format `{s}`, expected csv or jsonl")),
+        }
+    }
+}
+
+/// One analysed chunk. Levels of silent chunks are -inf, written as `-inf` in CSV and
+/// `null` in JSON.
+#[derive(Serialize)]
+struct Record<'a> {
+    file: &'a str,
+    /// Start of the chunk in seconds from the start of the file
+    time: f64,
+    db: f32,
+    peak_db: f32,
+    level: f32,
+    momentary_lufs: f32,
+    short_term_lufs: f32,
+    integrated_lufs: f32,
+    lra: f32,
+    true_peak_dbtp: f32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    spectrum: Option<&'a [f32]>,
+}
+
+/// Writes per-chunk analysis of audio files as CSV or JSON Lines.
+pub struct Exporter<W> {
+    writer: W,
+    format: Format,
+    settings: Settings,
+    spectrum: bool,
+    header_written: bool,
+}
+
+impl<W: Write> Exporter<W> {
+    pub fn new(writer: W, format: Format, settings: Settings) -> Self {
+        Self {
+            writer,
+            format,
+            settings,
+            spectrum: false,
+            header_written: false,
+        }
+    }
+
+    /// Also write the normalised spectrum bands of every chunk.
+    pub fn spectrum(mut self, spectrum: bool) -> Self {
+        self.spectrum = spectrum;
+        self
+    }
+
+    /// Decodes and analyses a whole file, writing a record for every chunk. A trailing
+    /// partial chunk is analysed as it is.
+    pub fn export(&mut self, path: &Path) -> Result<()> {
+        let decoder = Track::open(path)?;
+        let sample_rate = decoder.sample_rate();
+        let channels = decoder.channels();
+        if sample_rate == 0 || channels == 0 {
+            bail!("cannot decode {}: no audio stream", path.display());
+        }
+        let mut analyzer = Analyzer::new(self.settings, sample_rate, channels);
+        let name = path.display().to_string();
+
+        let chunk_size = analyzer.chunk_size();
+        let mut chunk = Vec::with_capacity(chunk_size);
+        let mut samples = decoder.peekable();
+        let mut frames = 0;
+        while samples.peek().is_some() {
+            chunk.clear();
+            chunk.extend(samples.by_ref().take(chunk_size));
+            let frame = analyzer.process(&chunk);
+            let time = frames as f64 / sample_rate as f64;
+            self.write(&name, time, &frame)
+                .context("cannot write export")?;
+            frames += chunk.len() / channels as usize;
+        }
+        self.writer.flush().context("cannot write export")
+    }
+
+    fn write(&mut self, file: &str, time: f64, frame: &Frame) -> Result<()> {
+        let record = Record {
+            file,
+            time,
+            db: frame.db,
+            peak_db: frame.peak,
+            level: frame.level,
+            momentary_lufs: frame.loudness.momentary,
+            short_term_lufs: frame.loudness.short_term,
+            integrated_lufs: frame.loudness.integrated,
+            lra: frame.loudness.range,
+            true_peak_dbtp: frame.loudness.true_peak,
+            spectrum: self.spectrum.then_some(frame.spectrum.as_slice()),
+        };
+        match self.format {
+            Format::Csv => self.write_csv(&record)?,
+            Format::JsonLines => {
+                serde_json::to_writer(&mut self.writer, &record)?;
+                writeln!(self.writer)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn write_csv(&mut self, record: &Record) -> std::io::Result<()> {
+        if !self.header_written {
+            self.header_written = true;
+            write!(
+                self.writer,
+                "file,time,db,peak_db,level,momentary_lufs,short_term_lufs,integrated_lufs,lra,true_peak_dbtp"
+            )?;
+            for band in 0..record.spectrum.map_or(0, <[f32]>::len) {
+                write!(self.writer, ",band_{band}")?;
+            }
+            writeln!(self.writer)?;
+        }
+
+        write!(
+            self.writer,
+            "{},{:.3},{:.2},{:.2},{:.4},{:.2},{:.2},{:.2},{:.2},{:.2}",
+            csv_field(record.file),
+            record.time,
+            record.db,
+            record.peak_db,
+            record.level,
+            record.momentary_lufs,
+            record.short_term_lufs,
+            record.integrated_lufs,
+            record.lra,
+            record.true_peak_dbtp
+        )?;
+        for level in record.spectrum.unwrap_or_default() {
+            write!(self.writer, ",{level:.4}")?;
+        }
+        writeln!(self.writer)
+    }
+}
+
+/// Quotes a CSV field if it contains anything that would break the row.
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{scratch_dir, write_wav};
+    use serde_json::Value;
+    use std::{fs, path::PathBuf};
+
+    const RATE: u32 = 8000;
+
+    /// Half a second of silence, then half a second of a 500 Hz sine.
+    fn write_input(dir: &Path, name: &str) -> PathBuf {
+        let path = dir.join(name);
+        let samples: Vec<i16> = (0..RATE as usize)
+            .map(|i| {
+                if i < RATE as usize / 2 {
+                    0
+                } else {
+                    let t = i as f64 / RATE as f64;
+                    ((std::f64::consts::TAU * 500.0 * t).sin() * 8000.0) as i16
+                }
+            })
+            .collect();
+        write_wav(&path, RATE, 1, &samples);
+        path
+    }
+
+    fn export(path: &Path, format: Format, spectrum: bool) -> String {
+        let mut output = Vec::new();
+        Exporter::new(&mut output, format, Settings::default())
+            .spectrum(spectrum)
+            .export(path)
+            .unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn csv_has_a_row_per_chunk() {
+        let dir = scratch_dir("export-csv");
+        let path = write_input(&dir, "tone.wav");
+        let csv = export(&path, Format::Csv, false);
+        let mut lines = csv.lines();
+        assert_eq!(
+            lines.next(),
+            Some("file,time,db,peak_db,level,momentary_lufs,short_term_lufs,integrated_lufs,lra,true_peak_dbtp")
+        );
+        let rows: Vec<Vec<&str>> = lines.map(|l| l.split(',').collect()).collect();
+        // 20 chunks a second by default
+        assert_eq!(rows.len(), 20);
+        for (i, row) in rows.iter().enumerate() {
+            assert_eq!(row.len(), 10);
+            assert_eq!(row[0], path.display().to_string());
+            assert_eq!(row[1], format!("{:.3}", i as f64 * 0.05));
+        }
+        assert_eq!(rows[0][2], "-inf");
+        assert_eq!(rows[0][3], "-inf");
+        assert!(rows[19][2].parse::<f32>().unwrap() > -20.0);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn csv_adds_a_column_per_band() {
+        let dir = scratch_dir("export-bands");
+        let path = write_input(&dir, "tone.wav");
+        let csv = export(&path, Format::Csv, true);
+        let header: Vec<&str> = csv.lines().next().unwrap().split(',').collect();
+        let bands = header.len() - 10;
+        assert!(bands > 0);
+        for (band, name) in header[10..].iter().enumerate() {
+            assert_eq!(*name, format!("band_{band}"));
+        }
+        for row in csv.lines().skip(1) {
+            assert_eq!(row.split(',').count(), 10 + bands, "{row}");
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn csv_quotes_awkward_file_names() {
+        assert_eq!(csv_field("plain.wav"), "plain.wav");
+        assert_eq!(csv_field("a,b.wav"), "\"a,b.wav\"");
+        assert_eq!(csv_field("say \"hi\".wav"), "\"say \"\"hi\"\".wav\"");
+        assert_eq!(csv_field("two\nlines.wav"), "\"two\nlines.wav\"");
+
+        let dir = scratch_dir("export-quote");
+        let path = write_input(&dir, "a,b.wav");
+        let csv = export(&path, Format::Csv, false);
+        let row = csv.lines().nth(1).unwrap();
+        assert!(
+            row.starts_with(&format!("\"{}\",0.000,", path.display())),
+            "{row}"
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn json_lines_write_silence_as_null() {
+        let dir = scratch_dir("export-json");
+        let path = write_input(&dir, "tone.wav");
+        let records: Vec<Value> = export(&path, Format::JsonLines, false)
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect();
+        assert_eq!(records.len(), 20);
+        for (i, record) in records.iter().enumerate() {
+            assert_eq!(record["file"], path.display().to_string());
+            assert!((record["time"].as_f64().unwrap() - i as f64 * 0.05).abs() < 1e-9);
+            assert!(record.get("spectrum").is_none());
+        }
+        assert_eq!(records[0]["db"], Value::Null);
+        assert_eq!(records[0]["integrated_lufs"], Value::Null);
+        assert!(records[19]["db"].as_f64().unwrap() > -20.0);
+
+        let with_bands = export(&path, Format::JsonLines, true);
+        let record: Value = serde_json::from_str(with_bands.lines().next().unwrap()).unwrap();
+        assert!(!record["spectrum"].as_array().unwrap().is_empty());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn format_follows_the_extension() {
+        assert_eq!(Format::from_path(Path::new("out.jsonl")), Format::JsonLines);
+        assert_eq!(Format::from_path(Path::new("out.csv")), Format::Csv);
+        assert_eq!(Format::from_path(Path::new("-")), Format::Csv);
+        assert_eq!("json".parse(), Ok(Format::JsonLines));
+        assert!("xml".parse::<Format>().is_err());
+    }
+}
@@ -0,0 +1,128 @@
+//! Hand-off of analysed frames from an analysis thread to the thread drawing them, through
+//! a fixed-size lock-free ring so neither ever waits for the other.
+
+use crate::{Frame, History, Registry};
+use rtrb::{Consumer, Producer, RingBuffer};
+
+/// Creates a feed holding up to `capacity` frames that have not been received yet.
+pub fn channel(capacity: usize) -> (Sender, Receiver) {
+    let (producer, consumer) = RingBuffer::new(capacity.max(1));
+    (
+        Sender { producer },
+        Receiver {
+            consumer,
+            generation: 0,
+        },
+    )
+}
+
+/// The analysing end of a feed.
+pub struct Sender {
+    producer: Producer<(usize, Frame)>,
+}
+
+impl Sender {
+    /// Sends a frame, tagged with a generation that changes whenever the frames stop
+    /// following on from the earlier ones, e.g. the seek count of a track. If the
+    /// receiver has fallen so far behind that the feed is full, the frame is dropped and
+    /// false returned.
+    pub fn send(&mut self, generation: usize, frame: Frame) -> bool {
+        self.producer.push((generation, frame)).is_ok()
+    }
+}
+
+/// The drawing end of a feed.
+pub struct Receiver {
+    consumer: Consumer<(usize, Frame)>,
+    generation: usize,
+}
+
+impl Receiver {
+    /// Pushes every frame sent so far into `history` and feeds it to the visualisers,
+    /// clearing both first when the generation changes.
+    pub fn receive(&mut self, history: &mut History, visualizers: &mut Registry) {
+        while let Ok((generation, frame)) = self.consumer.pop() {
+            if generation != self.generation {
+                self.generation = generation;
+                history.clear();
+                visualizers.clear();
+            }
+            visualizers.update(&frame);
+            history.push(frame);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{visualizer::Scene, Visualizer};
+    use ratatui::{buffer::Buffer, layout::Rect};
+    use std::{cell::RefCell, rc::Rc};
+
+    /// Notes the levels it is fed, and a 0 whenever it is cleared.
+    struct Log(Rc<RefCell<Vec<f32>>>);
+
+    impl Visualizer for Log {
+        fn name(&self) -> &str {
+            "Log"
+        }
+
+        fn update(&mut self, frame: &Frame) {
+            self.0.borrow_mut().push(frame.level);
+        }
+
+        fn clear(&mut self) {
+            self.0.borrow_mut().push(0.0);
+        }
+
+        fn render(&self, _: &Scene, _: Rect, _: &mut Buffer) {}
+    }
+
+    fn frame(level: f32) -> Frame {
+        Frame {
+            level,
+            ..Frame::default()
+        }
+    }
+
+    #[test]
+    fn new_generation_starts_afresh() {
+        let (mut sender, mut receiver) = channel(8);
+        let mut history = History::new(10);
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let mut visualizers = Registry::new();
+        visualizers.register(Log(Rc::clone(&log)));
+
+        assert!(sender.send(0, frame(0.1)));
+        assert!(sender.send(0, frame(0.2)));
+        receiver.receive(&mut history, &mut visualizers);
+        assert_eq!(history.levels(), [0.1, 0.2]);
+
+        // A seek: what was drawn before it goes, whatever arrives in the same batch
+        assert!(sender.send(0, frame(0.3)));
+        assert!(sender.send(1, frame(0.7)));
+        assert!(sender.send(1, frame(0.8)));
+        receiver.receive(&mut history, &mut visualizers);
+        assert_eq!(history.levels(), [0.7, 0.8]);
+        assert_eq!(history.latest().level, 0.8);
+        assert_eq!(*log.borrow(), [0.1, 0.2, 0.3, 0.0, 0.7, 0.8]);
+
+        // Nothing new, nothing changes
+        receiver.receive(&mut history, &mut visualizers);
+        assert_eq!(history.levels(), [0.7, 0.8]);
+    }
+
+    #[test]
+    fn full_feed_drops_new_frames() {
+        let (mut sender, mut receiver) = channel(2);
+        let mut history = History::new(10);
+        let mut visualizers = Registry::new();
+        assert!(sender.send(0, frame(0.1)));
+        assert!(sender.send(0, frame(0.2)));
+        assert!(!sender.send(0, frame(0.3)));
+        receiver.receive(&mut history, &mut visualizers);
+        assert_eq!(history.levels(), [0.1, 0.2]);
+        assert!(sender.send(0, frame(0.4)));
+    }
+}
@@ -0,0 +1,29 @@
+//! Audio analysis and ratatui widgets behind the `audio-vis` terminal visualiser.
+//!
+//! An [`Analyzer`] turns chunks of interleaved samples into [`Frame`]s, a [`History`]
+//! keeps the recent frames around, and [`LevelChart`] draws that history with one of the
+//! [`Visualizer`]s in a [`Registry`]. Frames analysed on another thread reach the history
+//! through a lock-free [`feed`].
+
+pub mod analysis;
+pub mod audio;
+pub mod capture;
+pub mod config;
+pub mod export;
+pub mod feed;
+pub mod loudness;
+pub mod metadata;
+pub mod playlist;
+pub mod render;
+#[cfg(test)]
+mod testing;
+pub mod theme;
+pub mod visualizer;
+pub mod widget;
+
+pub use analysis::{Analyzer, Ballistics, Frame, History, Scale, Settings};
+pub use config::Config;
+pub use playlist::Playlist;
+pub use theme::Theme;
+pub use visualizer::{Registry, Visualizer};
+pub use widget::{BarLayout, LevelChart, Panes, PeakMeter, StatusBar};
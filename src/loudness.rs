@@ -0,0 +1,406 @@
+//! Loudness measurement following ITU-R BS.1770-4 and EBU R128 / Tech 3342: K-weighted
+//! momentary, short-term and integrated loudness, loudness range and true peak.
+
+use std::{collections::VecDeque, f64::consts::PI};
+
+const ABSOLUTE_GATE: f64 = -70.0; // LUFS below which blocks are ignored
+const INTEGRATED_GATE: f64 = -10.0; // LU below the ungated mean for integrated loudness
+const RANGE_GATE: f64 = -20.0; // LU below the ungated mean for loudness range
+const MOMENTARY_BLOCKS: usize = 4; // 100ms sub-blocks in the 400ms momentary window
+const SHORT_TERM_BLOCKS: usize = 30; // 100ms sub-blocks in the 3s short-term window
+const SHORT_TERM_STEP: usize = 10; // Sub-blocks between short-term values kept for the range
+const OVERSAMPLING: usize = 4; // True-peak interpolation factor
+const TAPS_PER_PHASE: usize = 12;
+const HISTOGRAM_STEP: f64 = 0.1; // LU per histogram bin
+const HISTOGRAM_BINS: usize = 1000; // Bins from the absolute gate up to +30 LUFS
+
+/// A loudness reading. Values are -inf until there is enough audio to measure.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Loudness {
+    /// Loudness of the last 400ms in LUFS
+    pub momentary: f32,
+    /// Loudness of the last 3s in LUFS
+    pub short_term: f32,
+    /// Gated loudness of everything measured so far in LUFS
+    pub integrated: f32,
+    /// Loudness range (LRA) of everything measured so far in LU
+    pub range: f32,
+    /// Highest 4x oversampled sample peak so far in dBTP
+    pub true_peak: f32,
+}
+
+impl Default for Loudness {
+    fn default() -> Self {
+        Self {
+            momentary: f32::NEG_INFINITY,
+            short_term: f32::NEG_INFINITY,
+            integrated: f32::NEG_INFINITY,
+            range: 0.0,
+            true_peak: f32::NEG_INFINITY,
+        }
+    }
+}
+
+/// Second order IIR filter, transposed direct form II.
+#[derive(Clone, Copy, Debug, Default)]
+struct Biquad {
+    b: [f64; 3],
+    a: [f64; 2],
+    z: [f64; 2],
+}
+
+impl Biquad {
+    fn process(&mut self, x: f64) -> f64 {
+        let y = self.b[0] * x + self.z[0];
+        self.z[0] = self.b[1] * x - self.a[0] * y + self.z[1];
+        self.z[1] = self.b[2] * x - self.a[1] * y;
+        y
+    }
+}
+
+/// The two K-weighting stages, a high shelf modelling the head followed by a high pass,
+/// with the BS.1770 coefficients recomputed for any sample rate.
+fn k_weighting(sample_rate: u32) -> [Biquad; 2] {
+    let rate = sample_rate as f64;
+
+    let (f0, gain, q) = (1681.974450955533, 3.999843853973347, 0.7071752369554196);
+    let k = (PI * f0 / rate).tan();
+    let vh = 10f64.powf(gain / 20.0);
+    let vb = vh.powf(0.4996667741545416);
+    let a0 = 1.0 + k / q + k * k;
+    let shelf = Biquad {
+        b: [
+            (vh + vb * k / q + k * k) / a0,
+            2.0 * (k * k - vh) / a0,
+            (vh - vb * k / q + k * k) / a0,
+        ],
+        a: [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+        z: [0.0; 2],
+    };
+
+    let (f0, q) = (38.13547087602444, 0.5003270373238773);
+    let k = (PI * f0 / rate).tan();
+    let a0 = 1.0 + k / q + k * k;
+    let high_pass = Biquad {
+        b: [1.0, -2.0, 1.0],
+        a: [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+        z: [0.0; 2],
+    };
+
+    [shelf, high_pass]
+}
+
+/// Weight of a channel in the sum: surrounds count 1.41, the LFE of a 5.1 mix not at all.
+fn channel_weight(channel: usize, channels: usize) -> f64 {
+    match (channels, channel) {
+        (6, 3) => 0.0,
+        (6, 4 | 5) | (5, 3 | 4) => 1.41,
+        _ => 1.0,
+    }
+}
+
+/// Loudness in LUFS of a weighted mean square.
+fn lufs(power: f64) -> f64 {
+    -0.691 + 10.0 * power.log10()
+}
+
+/// Blocks above the absolute gate counted in 0.1 LU bins, as libebur128 does, so gating
+/// everything measured so far takes the same time and memory however long the stream.
+/// Each bin also sums the power of its blocks, so means over whole bins are exact.
+#[derive(Clone, Debug)]
+struct Histogram {
+    counts: Vec<u64>,
+    powers: Vec<f64>,
+    count: u64,
+    power: f64,
+}
+
+impl Histogram {
+    fn new() -> Self {
+        Self {
+            counts: vec![0; HISTOGRAM_BINS],
+            powers: vec![0.0; HISTOGRAM_BINS],
+            count: 0,
+            power: 0.0,
+        }
+    }
+
+    fn bin(loudness: f64) -> usize {
+        (((loudness - ABSOLUTE_GATE) / HISTOGRAM_STEP).max(0.0) as usize).min(HISTOGRAM_BINS - 1)
+    }
+
+    /// Loudness in the middle of a bin.
+    fn level(bin: usize) -> f64 {
+        ABSOLUTE_GATE + (bin as f64 + 0.5) * HISTOGRAM_STEP
+    }
+
+    /// Counts a block by its mean square, unless it is below the absolute gate.
+    fn add(&mut self, power: f64) {
+        if lufs(power) > ABSOLUTE_GATE {
+            let bin = Self::bin(lufs(power));
+            self.counts[bin] += 1;
+            self.powers[bin] += power;
+            self.count += 1;
+            self.power += power;
+        }
+    }
+
+    /// First bin at or above the relative gate, `gate` LU below the mean of every block.
+    fn gate(&self, gate: f64) -> Option<usize> {
+        (self.count > 0).then(|| Self::bin(lufs(self.power / self.count as f64) + gate))
+    }
+}
+
+/// Polyphase windowed-sinc interpolator, one phase per oversampled position.
+fn interpolation_filter() -> [[f32; TAPS_PER_PHASE]; OVERSAMPLING] {
+    let len = OVERSAMPLING * TAPS_PER_PHASE;
+    let mut phases = [[0.0; TAPS_PER_PHASE]; OVERSAMPLING];
+    for n in 0..len {
+        let m = n as f64 - (len - 1) as f64 / 2.0;
+        let x = m / OVERSAMPLING as f64;
+        let sinc = if x == 0.0 {
+            1.0
+        } else {
+            (PI * x).sin() / (PI * x)
+        };
+        let window = 0.5 + 0.5 * (2.0 * PI * m / len as f64).cos();
+        phases[n % OVERSAMPLING][n / OVERSAMPLING] = (sinc * window) as f32;
+    }
+    phases
+}
+
+/// Running loudness measurement of one stream of interleaved 16-bit samples.
+pub struct Meter {
+    sample_rate: u32,
+    channels: usize,
+    weights: Vec<f64>,
+    filters: Vec<[Biquad; 2]>,
+    block_len: usize,
+    block_frames: usize,
+    block_power: f64,
+    /// Mean square of the latest 100ms sub-blocks, newest last
+    sub_blocks: VecDeque<f64>,
+    sub_block_count: usize,
+    /// Every 400ms gating block so far
+    gating_blocks: Histogram,
+    /// Every 3s window so far, one per second
+    short_terms: Histogram,
+    phases: [[f32; TAPS_PER_PHASE]; OVERSAMPLING],
+    recent: Vec<[f32; TAPS_PER_PHASE]>,
+    true_peak: f32,
+}
+
+impl Meter {
+    pub fn new(sample_rate: u32, channels: u16) -> Self {
+        let channels = channels.max(1) as usize;
+        Self {
+            sample_rate,
+            channels,
+            weights: (0..channels)
+                .map(|channel| channel_weight(channel, channels))
+                .collect(),
+            filters: vec![k_weighting(sample_rate); channels],
+            block_len: (sample_rate as usize / 10).max(1),
+            block_frames: 0,
+            block_power: 0.0,
+            sub_blocks: VecDeque::with_capacity(SHORT_TERM_BLOCKS + 1),
+            sub_block_count: 0,
+            gating_blocks: Histogram::new(),
+            short_terms: Histogram::new(),
+            phases: interpolation_filter(),
+            recent: vec![[0.0; TAPS_PER_PHASE]; channels],
+            true_peak: 0.0,
+        }
+    }
+
+    /// Starts measuring afresh, e.g. after a seek.
+    pub fn reset(&mut self) {
+        *self = Self::new(self.sample_rate, self.channels as u16);
+    }
+
+    /// Feeds interleaved samples through the meter.
+    pub fn process(&mut self, samples: &[i16]) {
+        for frame in samples.chunks_exact(self.channels) {
+            let mut power = 0.0;
+            for (channel, &s) in frame.iter().enumerate() {
+                let x = s as f32 / i16::MAX as f32;
+                self.true_peak = self.true_peak.max(self.interpolate(channel, x));
+
+                let [shelf, high_pass] = &mut self.filters[channel];
+                let y = high_pass.process(shelf.process(x as f64));
+                power += self.weights[channel] * y * y;
+            }
+
+            self.block_power += power;
+            self.block_frames += 1;
+            if self.block_frames == self.block_len {
+                self.end_sub_block();
+            }
+        }
+    }
+
+    /// Pushes a sample into a channel's interpolator and returns the largest magnitude
+    /// among it and the oversampled points leading up to it.
+    fn interpolate(&mut self, channel: usize, x: f32) -> f32 {
+        let recent = &mut self.recent[channel];
+        recent.rotate_right(1);
+        recent[0] = x;
+        self.phases
+            .iter()
+            .map(|taps| {
+                taps.iter()
+                    .zip(recent.iter())
+                    .map(|(t, s)| t * s)
+                    .sum::<f32>()
+                    .abs()
+            })
+            .fold(x.abs(), f32::max)
+    }
+
+    fn end_sub_block(&mut self) {
+        self.sub_blocks
+            .push_back(self.block_power / self.block_frames as f64);
+        if self.sub_blocks.len() > SHORT_TERM_BLOCKS {
+            self.sub_blocks.pop_front();
+        }
+        self.block_power = 0.0;
+        self.block_frames = 0;
+        self.sub_block_count += 1;
+
+        // Gating blocks overlap by 75%, short-term windows for the range by two thirds
+        if let Some(power) = self.window(MOMENTARY_BLOCKS) {
+            self.gating_blocks.add(power);
+        }
+        if self.sub_block_count >= SHORT_TERM_BLOCKS
+            && (self.sub_block_count - SHORT_TERM_BLOCKS).is_multiple_of(SHORT_TERM_STEP)
+        {
+            if let Some(power) = self.window(SHORT_TERM_BLOCKS) {
+                self.short_terms.add(power);
+            }
+        }
+    }
+
+    /// Mean square of the latest `blocks` sub-blocks, if there are that many yet.
+    fn window(&self, blocks: usize) -> Option<f64> {
+        (self.sub_blocks.len() >= blocks)
+            .then(|| self.sub_blocks.iter().rev().take(blocks).sum::<f64>() / blocks as f64)
+    }
+
+    /// Gated integrated loudness in LUFS.
+    fn integrated(&self) -> f64 {
+        let blocks = &self.gating_blocks;
+        let Some(first) = blocks.gate(INTEGRATED_GATE) else {
+            return f64::NEG_INFINITY;
+        };
+        let count: u64 = blocks.counts[first..].iter().sum();
+        let power: f64 = blocks.powers[first..].iter().sum();
+        if count == 0 {
+            f64::NEG_INFINITY
+        } else {
+            lufs(power / count as f64)
+        }
+    }
+
+    /// Loudness range in LU: the spread between the 10th and 95th percentiles of the
+    /// gated short-term loudness.
+    fn range(&self) -> f64 {
+        let values = &self.short_terms;
+        let Some(first) = values.gate(RANGE_GATE) else {
+            return 0.0;
+        };
+        let counts = &values.counts[first..];
+        let count: u64 = counts.iter().sum();
+        if count == 0 {
+            return 0.0;
+        }
+        let percentile = |p: f64| {
+            let rank = ((count - 1) as f64 * p).round() as u64;
+            let mut seen = 0;
+            let bin = counts
+                .iter()
+                .position(|&c| {
+                    seen += c;
+                    seen > rank
+                })
+                .unwrap_or(counts.len() - 1);
+            Histogram::level(first + bin)
+        };
+        percentile(0.95) - percentile(0.10)
+    }
+
+    /// The current reading.
+    pub fn loudness(&self) -> Loudness {
+        let window = |blocks| self.window(blocks).map_or(f64::NEG_INFINITY, lufs) as f32;
+        Loudness {
+            momentary: window(MOMENTARY_BLOCKS),
+            short_term: window(SHORT_TERM_BLOCKS),
+            integrated: self.integrated() as f32,
+            range: self.range() as f32,
+            true_peak: 20.0 * self.true_peak.log10(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f64::consts::FRAC_PI_4;
+
+    const RATE: u32 = 48000;
+
+    /// `seconds` of a sine at `frequency` Hz and `dbfs` peak level, the same on every
+    /// channel, starting at `phase` radians.
+    fn sine(frequency: f64, dbfs: f64, phase: f64, seconds: f64, channels: usize) -> Vec<i16> {
+        let amplitude = 10f64.powf(dbfs / 20.0) * i16::MAX as f64;
+        (0..(seconds * RATE as f64) as usize)
+            .flat_map(|i| {
+                let t = i as f64 / RATE as f64;
+                let s = (std::f64::consts::TAU * frequency * t + phase).sin() * amplitude;
+                std::iter::repeat_n(s.round() as i16, channels)
+            })
+            .collect()
+    }
+
+    fn measure(channels: u16, samples: &[i16]) -> Loudness {
+        let mut meter = Meter::new(RATE, channels);
+        meter.process(samples);
+        meter.loudness()
+    }
+
+    #[test]
+    fn stereo_sine_at_minus_20_dbfs_is_minus_20_lufs() {
+        let loudness = measure(2, &sine(1000.0, -20.0, 0.0, 20.0, 2));
+        assert!((loudness.integrated + 20.0).abs() < 0.1, "{loudness:?}");
+        assert!((loudness.momentary + 20.0).abs() < 0.1, "{loudness:?}");
+        assert!((loudness.short_term + 20.0).abs() < 0.1, "{loudness:?}");
+    }
+
+    #[test]
+    fn mono_sine_at_full_scale_is_minus_3_lufs() {
+        let loudness = measure(1, &sine(1000.0, 0.0, 0.0, 20.0, 1));
+        assert!((loudness.integrated + 3.0).abs() < 0.1, "{loudness:?}");
+    }
+
+    #[test]
+    fn true_peak_is_found_between_samples() {
+        // Every sample is 3 dB below the crests they straddle
+        let loudness = measure(1, &sine(RATE as f64 / 4.0, -6.02, FRAC_PI_4, 1.0, 1));
+        assert!((loudness.true_peak + 6.02).abs() < 0.1, "{loudness:?}");
+    }
+
+    #[test]
+    fn range_spans_two_levels() {
+        // EBU Tech 3342 case 1: 20s at -20 dBFS then 20s at -30 dBFS
+        let mut samples = sine(1000.0, -20.0, 0.0, 20.0, 2);
+        samples.extend(sine(1000.0, -30.0, 0.0, 20.0, 2));
+        let loudness = measure(2, &samples);
+        assert!((loudness.range - 10.0).abs() < 0.2, "{loudness:?}");
+    }
+
+    #[test]
+    fn silence_has_no_loudness() {
+        let loudness = measure(2, &vec![0; RATE as usize * 4]);
+        assert_eq!(loudness.integrated, f32::NEG_INFINITY);
+        assert_eq!(loudness.range, 0.0);
+    }
+}
@@ -1,133 +1,779 @@
-use anyhow::Result;
+use anyhow::{bail, Context, Result};
+use audio_vis::{
+    audio::{Position, Tee, Track},
+    capture::{self, Capture},
+    config::{Command, Key},
+    export::{Exporter, Format},
+    feed,
+    metadata::Metadata,
+    render::Renderer,
+    theme::ColorSupport,
+    Analyzer, Ballistics, Config, History, LevelChart, Panes, Playlist, Registry, Scale, StatusBar,
+    Theme,
+};
+use clap::{
+    builder::{PossibleValuesParser, TypedValueParser},
+    Parser,
+};
 use crossterm::{
-    event::{self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode},
+    cursor::Show,
+    event::{
+        self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyModifiers, MouseButton,
+        MouseEventKind,
+    },
     execute,
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
 };
 use ratatui::{
     backend::CrosstermBackend,
-    layout::{Constraint, Direction, Layout},
-    style::{Color, Style},
-    widgets::{BarChart, Block, Borders},
+    layout::{Constraint, Direction, Layout, Rect},
+    style::Style,
+    widgets::{Block, Borders, Gauge, Paragraph},
     Terminal,
 };
-use rodio::{Decoder, OutputStream, Sink, Source};
+use rodio::{OutputStream, Sink, Source};
+use signal_hook::{
+    consts::{SIGINT, SIGTERM, SIGTSTP},
+    iterator::Signals,
+    low_level,
+};
 use std::{
     fs::File,
-    io::BufReader,
-    sync::{Arc, Mutex},
-    thread,
-    time::Duration,
+    io::{self, BufWriter, Stdout, Write},
+    path::{Path, PathBuf},
+    sync::mpsc::Receiver,
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
 };
 
-const WINDOW_SIZE: usize = 100; // Number of bars to display on the graph
+const SEEK_STEP: f64 = 5.0; // Seconds skipped by the arrow keys
+const VOLUME_STEP: f32 = 0.1; // Volume change per +/- press
+const MAX_VOLUME: f32 = 2.0;
+const FEED_SECONDS: usize = 2; // Analysis the display can fall behind by before frames are dropped
+
+/// Terminal audio visualiser
+#[derive(Parser)]
+#[command(version, about)]
+struct Args {
+    /// Audio files to play, in order. Directories are searched recursively
+    #[arg(required_unless_present_any = ["input", "list_inputs"], value_name = "FILE|DIR")]
+    files: Vec<PathBuf>,
+
+    /// Visualise a live input instead of playing files, from the default device or the
+    /// named one ("null" is a silent virtual device)
+    #[arg(short, long, value_name = "DEVICE", conflicts_with = "files")]
+    input: Option<Option<String>>,
+
+    /// List the available input devices and exit
+    #[arg(long)]
+    list_inputs: bool,
+
+    /// Play the tracks in random order
+    #[arg(short, long)]
+    shuffle: bool,
+
+    /// Analyse the files without playing them and write per-chunk levels to FILE
+    /// ("-" for stdout) instead
+    #[arg(short, long, value_name = "FILE", conflicts_with = "input")]
+    export: Option<PathBuf>,
+
+    /// Format of the export [default: jsonl for .json/.jsonl/.ndjson files, otherwise csv]
+    #[arg(long, value_name = "csv|jsonl", requires = "export")]
+    format: Option<Format>,
+
+    /// Include the spectrum bands in the export
+    #[arg(long, requires = "export")]
+    spectrum: bool,
+
+    /// Draw the visualisation of the files to an animated GIF (PATH ending in .gif) or
+    /// numbered PNGs in the directory PATH instead of playing them
+    #[arg(long, value_name = "PATH", conflicts_with_all = ["input", "export"])]
+    render: Option<PathBuf>,
+
+    /// Frames per second of audio to render
+    #[arg(long, default_value_t = 25, requires = "render", value_parser = clap::value_parser!(u32).range(1..=100))]
+    fps: u32,
+
+    /// Size of the rendered terminal in cells, each 8x16 pixels
+    #[arg(long, default_value = "80x24", value_name = "COLSxROWS", requires = "render", value_parser = parse_size)]
+    size: (u16, u16),
+
+    /// Visualisation to start with: levels, spectrum, channels, scope, meter or spectrogram
+    #[arg(short, long, default_value = "levels", value_parser = parse_mode)]
+    mode: String,
+
+    /// Colour theme [default: from config, or default]. Setting NO_COLOR turns colour off
+    /// whatever the theme
+    #[arg(short, long, value_parser = PossibleValuesParser::new(Theme::NAMES))]
+    theme: Option<String>,
+
+    /// Configuration file to use instead of ~/.config/audio-vis/config.toml
+    #[arg(short, long, value_name = "FILE")]
+    config: Option<PathBuf>,
+
+    /// Number of bars to display on the graph [default: from config, or 100]
+    #[arg(short, long, value_parser = clap::value_parser!(u16).range(1..))]
+    window_size: Option<u16>,
+
+    /// Analysis and redraw rate in updates per second [default: from config, or 20]
+    #[arg(short, long, value_parser = clap::value_parser!(u32).range(1..=1000))]
+    refresh_rate: Option<u32>,
+
+    /// Level in dB drawn as an empty bar [default: from config, or -60]
+    #[arg(short = 'f', long, allow_hyphen_values = true, value_parser = parse_db_floor)]
+    db_floor: Option<f32>,
+
+    /// How levels map onto bar heights [default: from config, or db]
+    #[arg(long, value_parser = PossibleValuesParser::new(Scale::NAMES).map(|s| Scale::named(&s).unwrap_or_default()))]
+    scale: Option<Scale>,
+
+    /// How quickly bars follow the signal: not smoothed, like a VU meter or like a peak
+    /// programme meter [default: from config, or none]
+    #[arg(long, value_parser = PossibleValuesParser::new(Ballistics::NAMES).map(|s| Ballistics::named(&s).unwrap_or_default()))]
+    ballistics: Option<Ballistics>,
+}
+
+impl Args {
+    /// The built-in visualisers, starting with the one asked for.
+    fn visualizers(&self) -> Registry {
+        let mut visualizers = Registry::default();
+        visualizers.select(&self.mode);
+        visualizers
+    }
+
+    /// Loads the configuration file and applies the command line overrides to it.
+    fn config(&self) -> Result<Config> {
+        let mut config = Config::load(self.config.as_deref())?;
+        config.check_views(&self.visualizers())?;
+        if let Some(theme) = &self.theme {
+            config.theme = theme.clone();
+        }
+        let settings = &mut config.analysis;
+        if let Some(window_size) = self.window_size {
+            settings.window_size = window_size as usize;
+        }
+        if let Some(refresh_rate) = self.refresh_rate {
+            settings.refresh_rate = refresh_rate;
+        }
+        if let Some(db_floor) = self.db_floor {
+            if db_floor >= settings.db_ceiling {
+                bail!(
+                    "--db-floor: must be below the ceiling of {} dB",
+                    settings.db_ceiling
+                );
+            }
+            settings.db_floor = db_floor;
+        }
+        if let Some(scale) = self.scale {
+            settings.scale = scale;
+        }
+        if let Some(ballistics) = self.ballistics {
+            settings.ballistics = ballistics;
+        }
+        Ok(config)
+    }
+}
+
+fn parse_db_floor(s: &str) -> Result<f32, String> {
+    let db: f32 = s.parse().map_err(|_| format!("`{s}` is not a number"))?;
+    if db.is_finite() && db < 0.0 {
+        Ok(db)
+    } else {
+        Err("must be a negative number of dB, e.g. -60".to_string())
+    }
+}
+
+fn parse_mode(s: &str) -> Result<String, String> {
+    let visualizers = Registry::default();
+    if visualizers.names().any(|name| name.eq_ignore_ascii_case(s)) {
+        return Ok(s.to_string());
+    }
+    let names: Vec<String> = visualizers.names().map(str::to_lowercase).collect();
+    let (last, rest) = names.split_last().expect("there are built-in visualisers");
+    Err(format!(
+        "unknown mode `{s}`, expected {} or {last}",
+        rest.join(", ")
+    ))
+}
+
+fn parse_size(s: &str) -> Result<(u16, u16), String> {
+    let error = || format!("`{s}` is not a size like 80x24");
+    let (columns, rows) = s.split_once(['x', 'X']).ok_or_else(error)?;
+    let columns: u16 = columns.parse().map_err(|_| error())?;
+    let rows: u16 = rows.parse().map_err(|_| error())?;
+    if columns < 3 || rows < 3 {
+        return Err("must be at least 3x3".to_string());
+    }
+    Ok((columns, rows))
+}
 
 fn main() -> Result<()> {
-    // Setup terminal
-    enable_raw_mode()?;
-    let mut stdout = std::io::stdout();
-    execute!(stdout, EnterAlternateScreen, EnableMouseCapture)?;
-    let backend = CrosstermBackend::new(stdout);
-    let mut terminal = Terminal::new(backend)?;
+    let args = Args::parse();
+    let config = args.config()?;
+
+    if args.list_inputs {
+        for name in capture::input_devices()? {
+            println!("{name}");
+        }
+        return Ok(());
+    }
+
+    if let Some(device) = &args.input {
+        let (capture, receiver) = Capture::open(device.as_deref())?;
+        let mut tui = Tui::new()?;
+        return monitor(&mut tui, &config, &capture, receiver, args.visualizers());
+    }
+
+    // Check the named files up front so bad paths are reported before the terminal is
+    // taken over. Files found in directories are only skipped if they fail to decode.
+    let mut playlist = Playlist::scan(&args.files)?;
+    for path in args.files.iter().filter(|p| !p.is_dir()) {
+        Track::open(path)?;
+    }
+    if let Some(output) = &args.export {
+        return export(&args, &config, &playlist, output);
+    }
+    if let Some(output) = &args.render {
+        return render(&args, &config, &playlist, output);
+    }
+    if args.shuffle {
+        playlist.shuffle(&mut rand::thread_rng());
+    }
+
+    let mut tui = Tui::new()?;
 
     // Setup audio
     let (_stream, stream_handle) = OutputStream::try_default()?;
-    let sink = Sink::try_new(&stream_handle)?;
-    let file = File::open("src/pink.mp3")?;
-    let source = Decoder::new(BufReader::new(file))?;
-    let sample_rate = source.sample_rate();
-    let channels = source.channels();
-    let samples: Arc<Vec<i16>> = Arc::new(source.collect());
-
-    let audio_levels = Arc::new(Mutex::new(Vec::new()));
-    let audio_levels_clone = Arc::clone(&audio_levels);
-    let samples_clone = Arc::clone(&samples);
-
-    // Spawn a thread for audio processing
-    thread::spawn(move || {
-        let chunk_size = sample_rate as usize / 20; // 50ms chunks
-        for chunk in samples_clone.chunks(chunk_size) {
-            let rms: f32 = (chunk
-                .iter()
-                .map(|&s| (s as f32 / i16::MAX as f32).powi(2))
-                .sum::<f32>()
-                / chunk.len() as f32)
-                .sqrt();
-            let db = 20.0 * rms.log10();
-            let normalized_db = ((db + 60.0) / 60.0).clamp(0.0, 1.0);
-
-            let mut levels = audio_levels_clone.lock().unwrap();
-            levels.push(normalized_db);
-            if levels.len() > WINDOW_SIZE {
-                levels.remove(0);
-            }
-
-            thread::sleep(Duration::from_millis(50));
+
+    let mut visualizers = args.visualizers();
+    while let Some(path) = playlist.current() {
+        let track = match Track::open(path) {
+            Ok(track) => track,
+            Err(err) if args.files.iter().any(|f| f == path) => return Err(err),
+            Err(_) => {
+                playlist.remove_current();
+                continue;
+            }
+        };
+        let name = path.file_name().map_or_else(
+            || path.display().to_string(),
+            |n| n.to_string_lossy().into(),
+        );
+        let metadata = Metadata::read(path);
+        let heading = format!(
+            "[{}/{}] {}",
+            playlist.index() + 1,
+            playlist.tracks().len(),
+            metadata.heading().unwrap_or(name)
+        );
+
+        let sink = Sink::try_new(&stream_handle)?;
+        match play(
+            &mut tui,
+            &config,
+            &sink,
+            track,
+            &metadata,
+            &heading,
+            &mut visualizers,
+        )? {
+            Action::Next => {
+                if !playlist.advance() {
+                    break;
+                }
+            }
+            Action::Previous => playlist.back(),
+            Action::Quit => break,
+        }
+    }
+
+    Ok(())
+}
+
+/// Writes the analysis of every track in the playlist to `output` without playing it.
+/// Files found in directories that fail to decode are reported and skipped.
+fn export(args: &Args, config: &Config, playlist: &Playlist, output: &Path) -> Result<()> {
+    let format = args.format.unwrap_or_else(|| Format::from_path(output));
+    let writer: Box<dyn Write> = if output == Path::new("-") {
+        Box::new(io::stdout().lock())
+    } else {
+        let file =
+            File::create(output).with_context(|| format!("cannot create {}", output.display()))?;
+        Box::new(BufWriter::new(file))
+    };
+
+    let mut exporter = Exporter::new(writer, format, config.analysis).spectrum(args.spectrum);
+    for path in playlist.tracks() {
+        if let Err(err) = exporter.export(path) {
+            if args.files.contains(path) {
+                return Err(err);
+            }
+            eprintln!("skipping {}: {err:#}", path.display());
+        }
+    }
+    Ok(())
+}
+
+/// Draws the visualisation of every track in the playlist to images without playing it.
+fn render(args: &Args, config: &Config, playlist: &Playlist, output: &Path) -> Result<()> {
+    let (columns, rows) = args.size;
+    let mut renderer = Renderer::new(config, output, columns, rows)?
+        .visualizers(args.visualizers())
+        .fps(args.fps);
+    for path in playlist.tracks() {
+        if let Err(err) = renderer.render(path) {
+            if args.files.contains(path) {
+                return Err(err);
+            }
+            eprintln!("skipping {}: {err:#}", path.display());
+        }
+    }
+    eprintln!(
+        "{} frames written to {}",
+        renderer.frames(),
+        output.display()
+    );
+    Ok(())
+}
+
+/// Owns the terminal while the visualiser is on screen. It is put back the way it was
+/// when this is dropped, so early returns and errors cannot leave it in raw mode, and by
+/// a panic hook for panics.
+struct Tui {
+    terminal: Terminal<CrosstermBackend<Stdout>>,
+    signals: Signals,
+    /// Where the progress bar was last drawn, if anywhere
+    progress: Option<Rect>,
+    /// Whether the mouse was pressed on the progress bar and is still down
+    dragging: bool,
+    /// Index of the configured view shown, or `None` for the single chart
+    view: Option<usize>,
+}
+
+/// Something the user asked for.
+enum Input {
+    Command(Command),
+    /// Jump to a fraction of the way through the track
+    Seek(f64),
+}
+
+impl Tui {
+    fn new() -> Result<Self> {
+        let signals = Signals::new([SIGINT, SIGTERM, SIGTSTP])?;
+        // Only a panic on this thread ends the program; others are reported by the loops
+        let owner = thread::current().id();
+        let hook = std::panic::take_hook();
+        std::panic::set_hook(Box::new(move |info| {
+            if thread::current().id() == owner {
+                let _ = Self::leave();
+            }
+            hook(info);
+        }));
+
+        Self::enter()?;
+        let terminal = Terminal::new(CrosstermBackend::new(io::stdout()))?;
+        Ok(Self {
+            terminal,
+            signals,
+            progress: None,
+            dragging: false,
+            view: None,
+        })
+    }
+
+    fn enter() -> io::Result<()> {
+        enable_raw_mode()?;
+        execute!(io::stdout(), EnterAlternateScreen, EnableMouseCapture)
+    }
+
+    fn leave() -> io::Result<()> {
+        disable_raw_mode()?;
+        execute!(
+            io::stdout(),
+            LeaveAlternateScreen,
+            DisableMouseCapture,
+            Show
+        )
+    }
+
+    /// Moves on to the next of `views` configured views, or back to the single chart after
+    /// the last.
+    fn next_view(&mut self, views: usize) {
+        self.view = match self.view {
+            None if views > 0 => Some(0),
+            Some(i) if i + 1 < views => Some(i + 1),
+            _ => None,
+        };
+    }
+
+    /// Hands the terminal back to the shell and stops the process, taking the terminal
+    /// over again once it is resumed.
+    fn suspend(&mut self) -> Result<()> {
+        Self::leave()?;
+        low_level::emulate_default_handler(SIGTSTP)?;
+        Self::enter()?;
+        self.terminal.clear()?;
+        Ok(())
+    }
+
+    /// Waits up to `timeout` for a key press or mouse action and returns what it asks for.
+    /// Ctrl-C and termination signals quit, Ctrl-Z and SIGTSTP suspend. Clicking or
+    /// dragging along the progress bar seeks and the scroll wheel changes the volume.
+    fn poll(&mut self, config: &Config, timeout: Duration) -> Result<Option<Input>> {
+        let signals: Vec<_> = self.signals.pending().collect();
+        for signal in signals {
+            match signal {
+                SIGTSTP => self.suspend()?,
+                _ => return Ok(Some(Input::Command(Command::Quit))),
+            }
+        }
+
+        if !event::poll(timeout)? {
+            return Ok(None);
+        }
+        let key = match event::read()? {
+            Event::Key(key) => key,
+            Event::Mouse(mouse) => return Ok(self.mouse(mouse.kind, mouse.column, mouse.row)),
+            _ => return Ok(None),
+        };
+        if key.modifiers.contains(KeyModifiers::CONTROL) {
+            match key.code {
+                KeyCode::Char('c') => return Ok(Some(Input::Command(Command::Quit))),
+                KeyCode::Char('z') => {
+                    self.suspend()?;
+                    return Ok(None);
+                }
+                _ => {}
+            }
+        }
+        Ok(command(config, key.code).map(Input::Command))
+    }
+
+    fn mouse(&mut self, kind: MouseEventKind, column: u16, row: u16) -> Option<Input> {
+        match kind {
+            MouseEventKind::ScrollUp => return Some(Input::Command(Command::VolumeUp)),
+            MouseEventKind::ScrollDown => return Some(Input::Command(Command::VolumeDown)),
+            MouseEventKind::Up(MouseButton::Left) => {
+                self.dragging = false;
+                return None;
+            }
+            _ => {}
+        }
+
+        let bar = self.progress.filter(|bar| bar.width > 0)?;
+        let on_bar =
+            (bar.left()..bar.right()).contains(&column) && (bar.top()..bar.bottom()).contains(&row);
+        match kind {
+            MouseEventKind::Down(MouseButton::Left) if on_bar => self.dragging = true,
+            MouseEventKind::Drag(MouseButton::Left) if self.dragging => {}
+            _ => return None,
+        }
+        // Aim for the middle of the cell, so the last one reaches the end
+        let cell = column.clamp(bar.left(), bar.right() - 1) - bar.left();
+        Some(Input::Seek((cell as f64 + 0.5) / bar.width as f64))
+    }
+}
+
+impl Drop for Tui {
+    fn drop(&mut self) {
+        let _ = Self::leave();
+    }
+}
+
+/// Draws a frame and notes where the progress bar went.
+fn draw(
+    tui: &mut Tui,
+    config: &Config,
+    theme: &Theme,
+    history: &mut History,
+    visualizers: &Registry,
+    heading: &str,
+    status: StatusBar,
+) -> Result<()> {
+    let progress = status.progress();
+    let view = tui.view.and_then(|i| config.views.get(i));
+    let mut progress_area = None;
+    tui.terminal.draw(|f| {
+        let chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .margin(1)
+            .constraints(
+                [
+                    Constraint::Length(1),
+                    Constraint::Min(0),
+                    Constraint::Length(progress.is_some().into()),
+                    Constraint::Length(1),
+                ]
+                .as_ref(),
+            )
+            .split(f.size());
+        f.render_widget(Paragraph::new(heading), chunks[0]);
+        if let Some(ratio) = progress {
+            let gauge = Gauge::default()
+                .ratio(ratio)
+                .label("")
+                .use_unicode(true)
+                .gauge_style(Style::default().fg(theme.accent()));
+            f.render_widget(gauge, chunks[2]);
+            progress_area = Some(chunks[2]);
+        }
+        f.render_widget(status.db(history.latest().db), chunks[3]);
+
+        // Keep one chunk per column, enough for any of the built-in visualisers in any pane
+        history.set_capacity(chunks[1].width.saturating_sub(2).max(1) as usize);
+        let chart = LevelChart::new(history)
+            .bar_count(config.analysis.window_size)
+            .bar_widths(config.layout.min_bar_width, config.layout.max_bar_width)
+            .theme(theme.clone());
+        let block = Block::default()
+            .borders(Borders::ALL)
+            .border_style(theme.border_style());
+        match view {
+            Some(view) => f.render_widget(
+                Panes::new(&view.pane, visualizers, chart.block(block)),
+                chunks[1],
+            ),
+            None => {
+                let visualizer = visualizers.current();
+                let title = format!("{} - {}", config.title, visualizer.name());
+                f.render_widget(
+                    chart.visualizer(visualizer).block(block.title(title)),
+                    chunks[1],
+                );
+            }
+        }
+    })?;
+    tui.progress = progress_area;
+    Ok(())
+}
+
+/// A thread analysing sample buffers, and the frames it has sent.
+struct Analysis {
+    feed: feed::Receiver,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Analysis {
+    /// Takes the frames analysed since last time, failing if the thread has panicked.
+    fn receive(&mut self, history: &mut History, visualizers: &mut Registry) -> Result<()> {
+        self.feed.receive(history, visualizers);
+        if self.thread.as_ref().is_some_and(JoinHandle::is_finished) {
+            if let Some(Err(panic)) = self.thread.take().map(JoinHandle::join) {
+                let message = panic
+                    .downcast_ref::<&str>()
+                    .copied()
+                    .or_else(|| panic.downcast_ref::<String>().map(String::as_str))
+                    .unwrap_or("unknown panic");
+                bail!("analysis stopped: {message}");
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Spawns a thread that analyses sample buffers as they arrive, sending a frame for
+/// every complete chunk. If the buffers come from a seekable track, its position is used
+/// to start afresh after each seek. The thread ends with the queue.
+fn analyze(
+    mut analyzer: Analyzer,
+    receiver: Receiver<Vec<i16>>,
+    position: Option<Position>,
+) -> Analysis {
+    let capacity = analyzer.settings().refresh_rate as usize * FEED_SECONDS;
+    let (mut frames, feed) = feed::channel(capacity);
+    let thread = thread::spawn(move || {
+        let chunk_size = analyzer.chunk_size();
+        let mut seeks = position.as_ref().map_or(0, Position::seeks);
+        let mut pending = Vec::with_capacity(chunk_size * 2);
+        for buffer in receiver {
+            let current = position.as_ref().map_or(0, Position::seeks);
+            if current != seeks {
+                seeks = current;
+                pending.clear();
+                analyzer.reset();
+            }
+
+            pending.extend_from_slice(&buffer);
+            while pending.len() >= chunk_size {
+                let frame = analyzer.process(&pending[..chunk_size]);
+                pending.drain(..chunk_size);
+                frames.send(seeks, frame);
+            }
         }
     });
+    Analysis {
+        feed,
+        thread: Some(thread),
+    }
+}
+
+/// Visualises a live input until the user quits.
+fn monitor(
+    tui: &mut Tui,
+    config: &Config,
+    capture: &Capture,
+    receiver: Receiver<Vec<i16>>,
+    mut visualizers: Registry,
+) -> Result<()> {
+    let settings = config.analysis;
+    let interval = Duration::from_secs(1) / settings.refresh_rate;
+    let theme = config.theme(ColorSupport::detect());
+
+    let mut history = History::new(settings.window_size);
+    let analyzer = Analyzer::new(settings, capture.sample_rate(), capture.channels());
+    let mut analysis = analyze(analyzer, receiver, None);
+    let heading = format!("input: {}", capture.name());
+    let started = Instant::now();
 
-    sink.append(rodio::buffer::SamplesBuffer::new(
-        channels,
-        sample_rate,
-        samples.to_vec(),
-    ));
+    // Main loop
+    loop {
+        analysis.receive(&mut history, &mut visualizers)?;
+        let status =
+            StatusBar::new(capture.sample_rate(), capture.channels()).time(started.elapsed(), None);
+        draw(
+            tui,
+            config,
+            &theme,
+            &mut history,
+            &visualizers,
+            &heading,
+            status,
+        )?;
+
+        match tui.poll(config, interval)? {
+            Some(Input::Command(Command::Quit)) => return Ok(()),
+            Some(Input::Command(Command::NextMode)) => visualizers.next(),
+            Some(Input::Command(Command::NextView)) => tui.next_view(config.views.len()),
+            _ => {}
+        }
+    }
+}
+
+/// What to do once a track stops playing
+enum Action {
+    Next,
+    Previous,
+    Quit,
+}
+
+/// Plays one track while drawing its levels, until it ends or the user moves on.
+fn play(
+    tui: &mut Tui,
+    config: &Config,
+    sink: &Sink,
+    track: Track,
+    metadata: &Metadata,
+    heading: &str,
+    visualizers: &mut Registry,
+) -> Result<Action> {
+    let settings = config.analysis;
+    let interval = Duration::from_secs(1) / settings.refresh_rate;
+    let theme = config.theme(ColorSupport::detect());
+
+    let sample_rate = track.sample_rate();
+    let channels = track.channels();
+    let position = track.position();
+    let total = metadata.duration.or(track.total_duration());
+    let (tee, receiver) = Tee::new(track);
+
+    let mut history = History::new(settings.window_size);
+    visualizers.clear();
+    let analyzer = Analyzer::new(settings, sample_rate, channels);
+    let mut analysis = analyze(analyzer, receiver, Some(position.clone()));
+
+    sink.append(tee);
     sink.play();
 
-    // Create a vector of static strings for labels
-    let labels: Vec<String> = (0..WINDOW_SIZE).map(|i| i.to_string()).collect();
+    // Moves playback by a number of seconds, keeping to whole frames
+    let seek_by = |seconds: f64| {
+        let frame = channels as usize;
+        let step = (seconds.abs() * sample_rate as f64) as usize * frame;
+        let current = position.target();
+        let target = if seconds < 0.0 {
+            current.saturating_sub(step)
+        } else {
+            current + step
+        };
+        position.seek(target / frame * frame);
+    };
+    let seek_to = |fraction: f64| {
+        if let Some(total) = total {
+            let frames = total.as_secs_f64() * fraction * sample_rate as f64;
+            position.seek(frames as usize * channels as usize);
+        }
+    };
 
     // Main loop
     loop {
-        terminal.draw(|f| {
-            let chunks = Layout::default()
-                .direction(Direction::Vertical)
-                .margin(1)
-                .constraints([Constraint::Percentage(100)].as_ref())
-                .split(f.size());
-
-            let levels = audio_levels.lock().unwrap();
-            let bar_data: Vec<(&str, u64)> = levels
-                .iter()
-                .enumerate()
-                .map(|(i, &level)| (labels[i].as_str(), (level * 100.0) as u64))
-                .collect();
-
-            let barchart = BarChart::default()
-                .block(
-                    Block::default()
-                        .title("Audio Visualization")
-                        .borders(Borders::ALL),
-                )
-                .data(&bar_data)
-                .bar_width(1)
-                .bar_gap(0)
-                .bar_style(Style::default().fg(Color::Yellow))
-                .value_style(Style::default().fg(Color::Black).bg(Color::Yellow));
-
-            f.render_widget(barchart, chunks[0]);
-        })?;
-
-        if event::poll(Duration::from_millis(50))? {
-            if let Event::Key(key) = event::read()? {
-                if let KeyCode::Char('q') = key.code {
-                    break;
-                }
+        analysis.receive(&mut history, visualizers)?;
+        let played = position.samples() / channels.max(1) as usize;
+        let elapsed = Duration::from_secs_f64(played as f64 / sample_rate.max(1) as f64);
+        let status = StatusBar::new(sample_rate, channels)
+            .codec(metadata.codec.as_deref())
+            .time(elapsed, total)
+            .paused(sink.is_paused());
+        draw(
+            tui,
+            config,
+            &theme,
+            &mut history,
+            visualizers,
+            heading,
+            status,
+        )?;
+
+        let command = match tui.poll(config, interval)? {
+            Some(Input::Command(command)) => Some(command),
+            Some(Input::Seek(fraction)) => {
+                seek_to(fraction);
+                None
+            }
+            None => None,
+        };
+        match command {
+            Some(Command::Quit) => return Ok(Action::Quit),
+            Some(Command::NextTrack) => return Ok(Action::Next),
+            Some(Command::PreviousTrack) => return Ok(Action::Previous),
+            Some(Command::NextMode) => visualizers.next(),
+            Some(Command::NextView) => tui.next_view(config.views.len()),
+            Some(Command::Pause) if sink.is_paused() => sink.play(),
+            Some(Command::Pause) => sink.pause(),
+            Some(Command::SeekBack) => seek_by(-SEEK_STEP),
+            Some(Command::SeekForward) => seek_by(SEEK_STEP),
+            Some(Command::VolumeUp) => {
+                sink.set_volume((sink.volume() + VOLUME_STEP).min(MAX_VOLUME))
             }
+            Some(Command::VolumeDown) => sink.set_volume((sink.volume() - VOLUME_STEP).max(0.0)),
+            Some(Command::Restart) => {
+                position.seek(0);
+                sink.play();
+            }
+            None => {}
         }
 
         if sink.empty() {
-            break;
+            return Ok(Action::Next);
         }
     }
+}
 
-    // Restore terminal
-    disable_raw_mode()?;
-    execute!(
-        terminal.backend_mut(),
-        LeaveAlternateScreen,
-        DisableMouseCapture
-    )?;
-    terminal.show_cursor()?;
-
-    Ok(())
+/// Looks up the command bound to a key press in the configuration.
+fn command(config: &Config, code: KeyCode) -> Option<Command> {
+    let key = match code {
+        KeyCode::Char(c) => Key::Char(c),
+        KeyCode::Tab => Key::Tab,
+        KeyCode::Enter => Key::Enter,
+        KeyCode::Esc => Key::Esc,
+        KeyCode::Backspace => Key::Backspace,
+        KeyCode::Left => Key::Left,
+        KeyCode::Right => Key::Right,
+        KeyCode::Up => Key::Up,
+        KeyCode::Down => Key::Down,
+        KeyCode::Home => Key::Home,
+        KeyCode::End => Key::End,
+        KeyCode::PageUp => Key::PageUp,
+        KeyCode::PageDown => Key::PageDown,
+        _ => return None,
+    };
+    config.keys.command(key)
 }
@@ -0,0 +1,95 @@
+//! Tags and stream details of audio files, probed separately from decoding.
+
+use std::{fs::File, path::Path, time::Duration};
+use symphonia::core::{
+    formats::FormatOptions,
+    io::MediaSourceStream,
+    meta::{MetadataOptions, MetadataRevision, StandardTagKey},
+    probe::Hint,
+};
+
+/// What is known about a file beyond its samples. Anything that cannot be read is `None`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Metadata {
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    /// Short name of the codec, e.g. "MP3" or "PCM S16LE"
+    pub codec: Option<String>,
+    pub duration: Option<Duration>,
+}
+
+impl Metadata {
+    /// Reads the ID3, Vorbis comment or RIFF INFO tags and the stream details of a file.
+    /// Files that cannot be probed give empty metadata, as they may still play.
+    pub fn read(path: &Path) -> Self {
+        Self::probe(path).unwrap_or_default()
+    }
+
+    fn probe(path: &Path) -> Option<Self> {
+        let file = File::open(path).ok()?;
+        let stream = MediaSourceStream::new(Box::new(file), Default::default());
+        let mut hint = Hint::new();
+        if let Some(extension) = path.extension().and_then(|e| e.to_str()) {
+            hint.with_extension(extension);
+        }
+        let mut probed = symphonia::default::get_probe()
+            .format(
+                &hint,
+                stream,
+                &FormatOptions::default(),
+                &MetadataOptions::default(),
+            )
+            .ok()?;
+
+        let mut metadata = Self::default();
+        if let Some(track) = probed.format.default_track() {
+            let params = &track.codec_params;
+            metadata.codec = symphonia::default::get_codecs()
+                .get_codec(params.codec)
+                .map(|codec| codec.short_name.replace('_', " ").to_uppercase());
+            if let (Some(frames), Some(rate)) = (params.n_frames, params.sample_rate) {
+                metadata.duration = Some(Duration::from_secs_f64(frames as f64 / rate as f64));
+            }
+        }
+
+        // Tags in front of the container, e.g. ID3v2, then those inside it, which win
+        if let Some(revision) = probed.metadata.get().as_ref().and_then(|m| m.current()) {
+            metadata.add_tags(revision);
+        }
+        if let Some(revision) = probed.format.metadata().current() {
+            metadata.add_tags(revision);
+        }
+        Some(metadata)
+    }
+
+    fn add_tags(&mut self, revision: &MetadataRevision) {
+        for tag in revision.tags() {
+            let field = match tag.std_key {
+                Some(StandardTagKey::TrackTitle) => &mut self.title,
+                Some(StandardTagKey::Artist) => &mut self.artist,
+                Some(StandardTagKey::Album) => &mut self.album,
+                _ => continue,
+            };
+            // RIFF INFO strings keep their terminators
+            let value = tag.value.to_string();
+            let value = value.trim_matches(|c: char| c == '\0' || c.is_whitespace());
+            if !value.is_empty() {
+                *field = Some(value.to_string());
+            }
+        }
+    }
+
+    /// "Artist - Title (Album)", leaving out whatever is unknown, or `None` if there is no
+    /// title.
+    pub fn heading(&self) -> Option<String> {
+        let mut heading = self.title.clone()?;
+        if let Some(artist) = &self.artist {
+            heading = format!("{artist} - {heading}");
+        }
+        if let Some(album) = &self.album {
+            heading = format!("{heading} ({album})");
+        }
+        Some(heading)
+    }
+}
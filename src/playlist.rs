@@ -0,0 +1,212 @@
+use anyhow::{bail, Context, Result};
+use rand::{seq::SliceRandom, Rng};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// File extensions picked up when scanning directories.
+pub const EXTENSIONS: &[&str] = &["flac", "mp3", "ogg", "wav"];
+
+/// Ordered list of tracks with a cursor on the one playing.
+#[derive(Clone, Debug, Default)]
+pub struct Playlist {
+    tracks: Vec<PathBuf>,
+    current: usize,
+}
+
+impl Playlist {
+    /// Builds a playlist from files and directories, in the order given. Directories are
+    /// scanned recursively for files with a supported extension, sorted by path.
+    pub fn scan(paths: &[PathBuf]) -> Result<Self> {
+        let mut tracks = Vec::new();
+        for path in paths {
+            if path.is_dir() {
+                scan_dir(path, &mut tracks)
+                    .with_context(|| format!("cannot scan {}", path.display()))?;
+            } else if path.exists() {
+                tracks.push(path.clone());
+            } else {
+                bail!("no such file or directory: {}", path.display());
+            }
+        }
+        if tracks.is_empty() {
+            bail!(
+                "no playable files found (looking for {})",
+                EXTENSIONS.join(", ")
+            );
+        }
+        Ok(Self { tracks, current: 0 })
+    }
+
+    pub fn shuffle<R: Rng>(&mut self, rng: &mut R) {
+        self.tracks.shuffle(rng);
+        self.current = 0;
+    }
+
+    pub fn tracks(&self) -> &[PathBuf] {
+        &self.tracks
+    }
+
+    /// Index of the current track.
+    pub fn index(&self) -> usize {
+        self.current
+    }
+
+    pub fn current(&self) -> Option<&Path> {
+        self.tracks.get(self.current).map(PathBuf::as_path)
+    }
+
+    /// Moves to the next track, returning `false` if the current one was the last.
+    pub fn advance(&mut self) -> bool {
+        if self.current + 1 < self.tracks.len() {
+            self.current += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Moves to the previous track, staying on the first one.
+    pub fn back(&mut self) {
+        self.current = self.current.saturating_sub(1);
+    }
+
+    /// Drops the current track, e.g. because it failed to decode. The cursor then points
+    /// at the track that followed it; if it was the last there is no current track.
+    pub fn remove_current(&mut self) {
+        if self.current < self.tracks.len() {
+            self.tracks.remove(self.current);
+        }
+    }
+}
+
+fn scan_dir(dir: &Path, tracks: &mut Vec<PathBuf>) -> Result<()> {
+    let mut entries = fs::read_dir(dir)?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<Result<Vec<_>, _>>()?;
+    entries.sort();
+
+    for path in entries {
+        // Symlinked directories are not followed, so link cycles cannot recurse forever
+        if fs::symlink_metadata(&path)?.is_dir() {
+            scan_dir(&path, tracks)?;
+        } else if path.is_file() && is_supported(&path) {
+            tracks.push(path);
+        }
+    }
+    Ok(())
+}
+
+fn is_supported(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| EXTENSIONS.iter().any(|s| s.eq_ignore_ascii_case(e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::scratch_dir;
+
+    /// A fresh directory holding empty files at `paths`, relative to it.
+    fn tree(name: &str, paths: &[&str]) -> PathBuf {
+        let dir = scratch_dir(name);
+        for path in paths {
+            let path = dir.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, []).unwrap();
+        }
+        dir
+    }
+
+    fn names(playlist: &Playlist, dir: &Path) -> Vec<String> {
+        playlist
+            .tracks()
+            .iter()
+            .map(|t| t.strip_prefix(dir).unwrap().display().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn scan_finds_supported_files_in_order() {
+        let dir = tree(
+            "scan",
+            &[
+                "b/2.MP3",
+                "b/1.flac",
+                "a.wav",
+                "notes.txt",
+                "c/d/3.ogg",
+                "e.wav",
+            ],
+        );
+        let playlist = Playlist::scan(&[dir.join("e.wav"), dir.clone()]).unwrap();
+        assert_eq!(
+            names(&playlist, &dir),
+            [
+                "e.wav",
+                "a.wav",
+                "b/1.flac",
+                "b/2.MP3",
+                "c/d/3.ogg",
+                "e.wav"
+            ]
+        );
+        assert_eq!(playlist.index(), 0);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn scan_rejects_missing_and_empty() {
+        let dir = tree("empty", &["notes.txt"]);
+        let error = Playlist::scan(std::slice::from_ref(&dir)).unwrap_err();
+        assert!(error.to_string().contains("no playable files"), "{error}");
+        let error = Playlist::scan(&[dir.join("missing.wav")]).unwrap_err();
+        assert!(error.to_string().contains("missing.wav"), "{error}");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    fn listed(tracks: &[&str]) -> Playlist {
+        Playlist {
+            tracks: tracks.iter().map(PathBuf::from).collect(),
+            current: 0,
+        }
+    }
+
+    #[test]
+    fn advance_and_back_stay_in_bounds() {
+        let mut playlist = listed(&["1.wav", "2.wav"]);
+        playlist.back();
+        assert_eq!(playlist.current(), Some(Path::new("1.wav")));
+        assert!(playlist.advance());
+        assert_eq!(playlist.current(), Some(Path::new("2.wav")));
+        assert!(!playlist.advance());
+        assert_eq!(playlist.current(), Some(Path::new("2.wav")));
+        playlist.back();
+        assert_eq!(playlist.current(), Some(Path::new("1.wav")));
+    }
+
+    #[test]
+    fn removing_moves_on_to_the_next_track() {
+        let mut playlist = listed(&["1.wav", "2.wav", "3.wav"]);
+        playlist.advance();
+        playlist.remove_current();
+        assert_eq!(playlist.current(), Some(Path::new("3.wav")));
+        assert_eq!(playlist.tracks().len(), 2);
+    }
+
+    #[test]
+    fn removing_the_last_track_leaves_none_current() {
+        let mut playlist = listed(&["1.wav", "2.wav"]);
+        playlist.advance();
+        playlist.remove_current();
+        assert_eq!(playlist.current(), None);
+        assert_eq!(playlist.tracks().len(), 1);
+
+        let mut playlist = listed(&["1.wav"]);
+        playlist.remove_current();
+        assert_eq!(playlist.current(), None);
+        assert!(playlist.tracks().is_empty());
+    }
+}
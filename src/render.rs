@@ -0,0 +1,494 @@
+//! Offline rendering of the visualisation to images, for sharing without a terminal.
+//!
+//! Files are analysed exactly as they would be while playing and the chart is drawn into
+//! a ratatui [`Buffer`] at a fixed frame rate, which is then rasterised cell by cell. Block,
+//! braille and box drawing characters are drawn as they would look in a terminal; there is
+//! no font, so text is drawn as placeholder bars. Nothing depends on timing or threads, so
+//! the same input always gives the same images.
+
+use crate::{
+    audio::Track,
+    theme::{self, ColorSupport},
+    Analyzer, Config, History, LevelChart, Registry, Theme,
+};
+use anyhow::{bail, Context, Result};
+use ratatui::{
+    buffer::Buffer,
+    layout::{Margin, Rect},
+    style::{Color, Modifier},
+    widgets::{Block, Borders, Widget},
+};
+use rodio::Source;
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    io::BufWriter,
+    path::{Path, PathBuf},
+};
+
+/// Size of a terminal cell in pixels.
+pub const CELL_WIDTH: usize = 8;
+pub const CELL_HEIGHT: usize = 16;
+
+const DEFAULT_FOREGROUND: [u8; 3] = [229, 229, 229];
+const DEFAULT_BACKGROUND: [u8; 3] = [0, 0, 0];
+
+/// An RGB image, 3 bytes per pixel, rows top to bottom.
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<u8>,
+}
+
+/// Where rendered frames go.
+enum Output {
+    Gif(gif::Encoder<BufWriter<File>>, Palette),
+    Png(PathBuf),
+}
+
+/// Renders the visualisation of audio files to an animated GIF or a directory of numbered
+/// PNGs. Several files are rendered one after another into the same output.
+pub struct Renderer<'a> {
+    config: &'a Config,
+    theme: Theme,
+    visualizers: Registry,
+    area: Rect,
+    fps: u32,
+    output: Output,
+    frames: usize,
+}
+
+impl<'a> Renderer<'a> {
+    /// Starts rendering `columns` x `rows` cells to `output`: a GIF if it ends in `.gif`,
+    /// otherwise a directory of PNGs that is created if needed.
+    pub fn new(config: &'a Config, output: &Path, columns: u16, rows: u16) -> Result<Self> {
+        let area = Rect::new(0, 0, columns, rows);
+        let width = columns as usize * CELL_WIDTH;
+        let height = rows as usize * CELL_HEIGHT;
+        // Images can show any colour, whatever the terminal
+        let theme = config.theme(ColorSupport::TrueColor);
+        let is_gif = output
+            .extension()
+            .is_some_and(|e| e.eq_ignore_ascii_case("gif"));
+
+        let output = if is_gif {
+            if width > u16::MAX as usize || height > u16::MAX as usize {
+                bail!("{columns}x{rows} cells is too large for a GIF");
+            }
+            let file = File::create(output)
+                .with_context(|| format!("cannot create {}", output.display()))?;
+            let mut encoder =
+                gif::Encoder::new(BufWriter::new(file), width as u16, height as u16, &[])?;
+            encoder.set_repeat(gif::Repeat::Infinite)?;
+            Output::Gif(encoder, Palette::new(&theme))
+        } else {
+            fs::create_dir_all(output)
+                .with_context(|| format!("cannot create {}", output.display()))?;
+            Output::Png(output.to_path_buf())
+        };
+
+        Ok(Self {
+            config,
+            theme,
+            visualizers: Registry::default(),
+            area,
+            fps: 25,
+            output,
+            frames: 0,
+        })
+    }
+
+    /// Visualisers to draw with, using the current one.
+    pub fn visualizers(mut self, visualizers: Registry) -> Self {
+        self.visualizers = visualizers;
+        self
+    }
+
+    /// Frames per second of audio.
+    pub fn fps(mut self, fps: u32) -> Self {
+        self.fps = fps.max(1);
+        self
+    }
+
+    /// Number of frames written so far.
+    pub fn frames(&self) -> usize {
+        self.frames
+    }
+
+    /// Analyses a whole file and writes a frame for every `1 / fps` seconds of it. Each
+    /// frame shows the chunks that had finished by then, as the terminal would.
+    pub fn render(&mut self, path: &Path) -> Result<()> {
+        let decoder = Track::open(path)?;
+        let sample_rate = decoder.sample_rate();
+        let channels = decoder.channels();
+        if sample_rate == 0 || channels == 0 {
+            bail!("cannot decode {}: no audio stream", path.display());
+        }
+        let settings = self.config.analysis;
+        let mut analyzer = Analyzer::new(settings, sample_rate, channels);
+        // One chunk per column inside the margin and border, as in the terminal
+        let mut history = History::new(self.area.width.saturating_sub(4).max(1) as usize);
+        self.visualizers.clear();
+        let name = path.file_name().map_or_else(
+            || path.display().to_string(),
+            |n| n.to_string_lossy().into(),
+        );
+
+        let chunk_size = analyzer.chunk_size();
+        let mut chunk = Vec::with_capacity(chunk_size);
+        let mut samples = decoder.peekable();
+        let mut frames = 0;
+        let mut image = 0u64;
+        while samples.peek().is_some() {
+            chunk.clear();
+            chunk.extend(samples.by_ref().take(chunk_size));
+            let frame = analyzer.process(&chunk);
+            self.visualizers.update(&frame);
+            history.push(frame);
+            frames += (chunk.len() / channels as usize) as u64;
+
+            // Images due by the end of this chunk, compared in whole samples
+            while image * sample_rate as u64 <= frames * self.fps as u64 {
+                let buffer = self.draw(&history, &name);
+                self.write(&rasterize(&buffer))?;
+                image += 1;
+            }
+        }
+        Ok(())
+    }
+
+    fn draw(&self, history: &History, name: &str) -> Buffer {
+        let mut buffer = Buffer::empty(self.area);
+        let block = Block::default()
+            .title(format!(
+                "{} - {} - {name}",
+                self.config.title,
+                self.visualizers.current().name()
+            ))
+            .borders(Borders::ALL)
+            .border_style(self.theme.border_style());
+        LevelChart::new(history)
+            .visualizer(self.visualizers.current())
+            .bar_count(self.config.analysis.window_size)
+            .bar_widths(
+                self.config.layout.min_bar_width,
+                self.config.layout.max_bar_width,
+            )
+            .theme(self.theme.clone())
+            .block(block)
+            .render(
+                self.area.inner(&Margin {
+                    vertical: 1,
+                    horizontal: 1,
+                }),
+                &mut buffer,
+            );
+        buffer
+    }
+
+    fn write(&mut self, image: &Image) -> Result<()> {
+        match &mut self.output {
+            Output::Gif(encoder, palette) => {
+                let mut frame = gif_frame(image, palette);
+                // Delays are in hundredths of a second, spread so they add up exactly
+                let at = |frame: usize| frame as u64 * 100 / self.fps as u64;
+                frame.delay = (at(self.frames + 1) - at(self.frames)) as u16;
+                encoder.write_frame(&frame)?;
+            }
+            Output::Png(dir) => {
+                let path = dir.join(format!("frame_{:05}.png", self.frames));
+                let file = File::create(&path)
+                    .with_context(|| format!("cannot create {}", path.display()))?;
+                let mut encoder = png::Encoder::new(
+                    BufWriter::new(file),
+                    image.width as u32,
+                    image.height as u32,
+                );
+                encoder.set_color(png::ColorType::Rgb);
+                encoder.set_depth(png::BitDepth::Eight);
+                encoder.write_header()?.write_image_data(&image.pixels)?;
+            }
+        }
+        self.frames += 1;
+        Ok(())
+    }
+}
+
+/// Colours a GIF frame is reduced to when it has more than 256: the theme's fixed colours
+/// and its gradients sampled evenly. Built once per output, so frames are quantised by
+/// looking up each distinct colour rather than by training a new palette every frame.
+struct Palette {
+    colors: Vec<[u8; 3]>,
+    /// Index of the nearest palette colour of every colour seen so far
+    nearest: HashMap<[u8; 3], u8>,
+}
+
+impl Palette {
+    fn new(theme: &Theme) -> Self {
+        let fixed = [theme.values, theme.border, theme.clip, Color::Black];
+        let mut colors = vec![DEFAULT_FOREGROUND, DEFAULT_BACKGROUND];
+        colors.extend(fixed.into_iter().filter_map(theme::rgb));
+        // Share what is left between the two gradients
+        let steps = (256 - colors.len()) / 2;
+        let level = |i: usize| i as f32 / (steps - 1) as f32;
+        let gradients = (0..steps)
+            .map(|i| theme.bar(level(i)))
+            .chain((0..steps).map(|i| theme.heat(level(i))));
+        for rgb in gradients.filter_map(theme::rgb) {
+            if !colors.contains(&rgb) {
+                colors.push(rgb);
+            }
+        }
+        Self {
+            colors,
+            nearest: HashMap::new(),
+        }
+    }
+
+    fn index(&mut self, rgb: [u8; 3]) -> u8 {
+        let colors = &self.colors;
+        *self.nearest.entry(rgb).or_insert_with(|| {
+            (0..colors.len())
+                .min_by_key(|&i| theme::distance(colors[i], rgb))
+                .unwrap_or(0) as u8
+        })
+    }
+
+    fn frame(&mut self, image: &Image) -> gif::Frame<'static> {
+        let mut last = None;
+        let indices: Vec<u8> = image
+            .pixels
+            .chunks_exact(3)
+            .map(|pixel| {
+                let rgb = [pixel[0], pixel[1], pixel[2]];
+                match last {
+                    Some((color, index)) if color == rgb => index,
+                    _ => {
+                        let index = self.index(rgb);
+                        last = Some((rgb, index));
+                        index
+                    }
+                }
+            })
+            .collect();
+        gif::Frame::from_palette_pixels(
+            image.width as u16,
+            image.height as u16,
+            indices,
+            self.colors.concat(),
+            None,
+        )
+    }
+}
+
+/// Builds a GIF frame, with an exact palette when the image has few enough colours and
+/// the theme's otherwise.
+fn gif_frame(image: &Image, fixed: &mut Palette) -> gif::Frame<'static> {
+    let (width, height) = (image.width as u16, image.height as u16);
+    let mut palette: HashMap<[u8; 3], u8> = HashMap::new();
+    let mut colors = Vec::new();
+    let mut indices = Vec::with_capacity(image.width * image.height);
+    let mut last = None;
+    for pixel in image.pixels.chunks_exact(3) {
+        let rgb = [pixel[0], pixel[1], pixel[2]];
+        // Neighbouring pixels are mostly the same colour
+        if let Some((color, index)) = last {
+            if color == rgb {
+                indices.push(index);
+                continue;
+            }
+        }
+        let index = match palette.get(&rgb) {
+            Some(&index) => index,
+            None if palette.len() < 256 => {
+                let index = palette.len() as u8;
+                palette.insert(rgb, index);
+                colors.extend_from_slice(&rgb);
+                index
+            }
+            None => return fixed.frame(image),
+        };
+        last = Some((rgb, index));
+        indices.push(index);
+    }
+    gif::Frame::from_palette_pixels(width, height, indices, colors, None)
+}
+
+/// Draws a buffer as it would appear in a terminal with [`CELL_WIDTH`] x [`CELL_HEIGHT`]
+/// pixel cells.
+pub fn rasterize(buffer: &Buffer) -> Image {
+    let area = buffer.area;
+    let width = area.width as usize * CELL_WIDTH;
+    let height = area.height as usize * CELL_HEIGHT;
+    let mut pixels = vec![0; width * height * 3];
+
+    for row in 0..area.height {
+        for column in 0..area.width {
+            let cell = buffer.get(area.x + column, area.y + row);
+            let (mut fg, mut bg) = (
+                theme::rgb(cell.fg).unwrap_or(DEFAULT_FOREGROUND),
+                theme::rgb(cell.bg).unwrap_or(DEFAULT_BACKGROUND),
+            );
+            if cell.modifier.contains(Modifier::REVERSED) {
+                std::mem::swap(&mut fg, &mut bg);
+            }
+            let glyph = Glyph::of(&cell.symbol);
+
+            for y in 0..CELL_HEIGHT {
+                for x in 0..CELL_WIDTH {
+                    let color = if glyph.covers(x, y) { fg } else { bg };
+                    let px = column as usize * CELL_WIDTH + x;
+                    let py = row as usize * CELL_HEIGHT + y;
+                    let offset = (py * width + px) * 3;
+                    pixels[offset..offset + 3].copy_from_slice(&color);
+                }
+            }
+        }
+    }
+
+    Image {
+        width,
+        height,
+        pixels,
+    }
+}
+
+/// Shape of a character, as far as rasterising goes.
+enum Glyph {
+    Empty,
+    /// Fraction of the cell filled from the bottom, in eighths
+    Lower(usize),
+    Upper,
+    /// Fraction of the cell filled from the left, in eighths
+    Left(usize),
+    Shade,
+    /// Braille dot pattern, bit `n` being dot `n + 1`
+    Braille(u8),
+    /// Box drawing line from the centre towards up, down, left and right
+    Lines([bool; 4]),
+    Text,
+}
+
+impl Glyph {
+    fn of(symbol: &str) -> Self {
+        let Some(c) = symbol.chars().next() else {
+            return Glyph::Empty;
+        };
+        match c {
+            ' ' => Glyph::Empty,
+            '█' => Glyph::Lower(8),
+            '▁'..='▇' => Glyph::Lower(c as usize - '▁' as usize + 1),
+            '▀' => Glyph::Upper,
+            '▉'..='▏' => Glyph::Left('▏' as usize - c as usize + 1),
+            '░' | '▒' | '▓' => Glyph::Shade,
+            '\u{2800}'..='\u{28ff}' => Glyph::Braille((c as u32 - 0x2800) as u8),
+            '─' | '━' => Glyph::Lines([false, false, true, true]),
+            '│' | '┃' => Glyph::Lines([true, true, false, false]),
+            '┌' | '╭' => Glyph::Lines([false, true, false, true]),
+            '┐' | '╮' => Glyph::Lines([false, true, true, false]),
+            '└' | '╰' => Glyph::Lines([true, false, false, true]),
+            '┘' | '╯' => Glyph::Lines([true, false, true, false]),
+            '├' => Glyph::Lines([true, true, false, true]),
+            '┤' => Glyph::Lines([true, true, true, false]),
+            '┬' => Glyph::Lines([false, true, true, true]),
+            '┴' => Glyph::Lines([true, false, true, true]),
+            '┼' => Glyph::Lines([true; 4]),
+            _ => Glyph::Text,
+        }
+    }
+
+    fn covers(&self, x: usize, y: usize) -> bool {
+        match *self {
+            Glyph::Empty => false,
+            Glyph::Lower(eighths) => y >= CELL_HEIGHT - CELL_HEIGHT * eighths / 8,
+            Glyph::Upper => y < CELL_HEIGHT / 2,
+            Glyph::Left(eighths) => x < CELL_WIDTH * eighths / 8,
+            Glyph::Shade => (x + y).is_multiple_of(2),
+            Glyph::Braille(dots) => {
+                let (dx, dy) = (x * 2 / CELL_WIDTH, y * 4 / CELL_HEIGHT);
+                let bit = match (dx, dy) {
+                    (0, 3) => 6,
+                    (1, 3) => 7,
+                    (0, dy) => dy,
+                    (_, dy) => dy + 3,
+                };
+                dots & (1 << bit) != 0
+            }
+            Glyph::Lines([up, down, left, right]) => {
+                let (cx, cy) = (CELL_WIDTH / 2, CELL_HEIGHT / 2);
+                let on_vertical = x == cx - 1 || x == cx;
+                let on_horizontal = y == cy - 1 || y == cy;
+                (on_vertical && ((up && y <= cy) || (down && y >= cy - 1)))
+                    || (on_horizontal && ((left && x <= cx) || (right && x >= cx - 1)))
+            }
+            Glyph::Text => (5..12).contains(&y) && (1..7).contains(&x),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{scratch_dir, write_wav};
+
+    /// Writes a mono WAV of a sweep from 100 Hz to 5 kHz, so the spectrogram fills with
+    /// gradient colours.
+    fn write_sweep(path: &Path, sample_rate: u32, seconds: u32) {
+        let count = sample_rate * seconds;
+        let duration = seconds as f64;
+        let mut phase = 0.0f64;
+        let samples: Vec<i16> = (0..count)
+            .map(|i| {
+                let t = i as f64 / sample_rate as f64;
+                let frequency = 100.0 * 50f64.powf(t / duration);
+                phase += std::f64::consts::TAU * frequency / sample_rate as f64;
+                (phase.sin() * 0.5 * i16::MAX as f64) as i16
+            })
+            .collect();
+        write_wav(path, sample_rate, 1, &samples);
+    }
+
+    fn render(input: &Path, output: &Path, mode: &str) -> usize {
+        let config = Config::default();
+        let mut visualizers = Registry::default();
+        assert!(visualizers.select(mode));
+        let mut renderer = Renderer::new(&config, output, 40, 12)
+            .unwrap()
+            .visualizers(visualizers)
+            .fps(10);
+        renderer.render(input).unwrap();
+        renderer.frames()
+    }
+
+    #[test]
+    fn renders_the_same_gif_every_time() {
+        let dir = scratch_dir("render");
+        let input = dir.join("sweep.wav");
+        write_sweep(&input, 8000, 2);
+
+        for mode in ["Levels", "Spectrogram"] {
+            let (first, second) = (dir.join("first.gif"), dir.join("second.gif"));
+            // One frame at the start and one for every tenth of a second after it
+            assert_eq!(render(&input, &first, mode), 21);
+            assert_eq!(render(&input, &second, mode), 21);
+            let bytes = fs::read(&first).unwrap();
+            assert!(bytes.starts_with(b"GIF89a"));
+            assert_eq!(bytes, fs::read(&second).unwrap(), "{mode} differs");
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn palette_holds_the_theme_colours() {
+        let theme = Theme::default();
+        let mut palette = Palette::new(&theme);
+        assert!(palette.colors.len() <= 256);
+        for level in [0.0, 0.37, 1.0] {
+            for color in [theme.bar(level), theme.heat(level)] {
+                let rgb = theme::rgb(color).unwrap();
+                let index = palette.index(rgb) as usize;
+                assert!(theme::distance(palette.colors[index], rgb) <= 12, "{rgb:?}");
+            }
+        }
+    }
+}
@@ -0,0 +1,305 @@
+//! Colour themes, and fitting their colours to what the terminal can show.
+
+use ratatui::style::{Color, Modifier, Style};
+use std::env;
+
+/// The xterm colours of the 16 ANSI palette entries.
+const ANSI: [[u8; 3]; 16] = [
+    [0, 0, 0],
+    [205, 0, 0],
+    [0, 205, 0],
+    [205, 205, 0],
+    [0, 0, 238],
+    [205, 0, 205],
+    [0, 205, 205],
+    [229, 229, 229],
+    [127, 127, 127],
+    [255, 0, 0],
+    [0, 255, 0],
+    [255, 255, 0],
+    [92, 92, 255],
+    [255, 0, 255],
+    [0, 255, 255],
+    [255, 255, 255],
+];
+const ANSI_COLORS: [Color; 16] = [
+    Color::Black,
+    Color::Red,
+    Color::Green,
+    Color::Yellow,
+    Color::Blue,
+    Color::Magenta,
+    Color::Cyan,
+    Color::Gray,
+    Color::DarkGray,
+    Color::LightRed,
+    Color::LightGreen,
+    Color::LightYellow,
+    Color::LightBlue,
+    Color::LightMagenta,
+    Color::LightCyan,
+    Color::White,
+];
+const CUBE_LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+/// How many colours a terminal can show, fewest first.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ColorSupport {
+    /// Only the default colours, as asked for by `NO_COLOR`
+    None,
+    /// The 16 ANSI colours
+    Ansi16,
+    /// The xterm 256 colour palette
+    Ansi256,
+    /// Any 24-bit colour
+    TrueColor,
+}
+
+impl ColorSupport {
+    /// Guesses from the environment: a non-empty `NO_COLOR` or a dumb terminal means no
+    /// colour, `COLORTERM=truecolor` or `24bit` any colour and a `TERM` ending in
+    /// `256color` the xterm palette.
+    pub fn detect() -> Self {
+        let var = |name| env::var(name).unwrap_or_default();
+        if !var("NO_COLOR").is_empty() || var("TERM") == "dumb" {
+            ColorSupport::None
+        } else if matches!(var("COLORTERM").as_str(), "truecolor" | "24bit") {
+            ColorSupport::TrueColor
+        } else if var("TERM").ends_with("256color") {
+            ColorSupport::Ansi256
+        } else {
+            ColorSupport::Ansi16
+        }
+    }
+
+    /// The nearest colour this terminal can show.
+    pub fn fit(self, color: Color) -> Color {
+        match (self, color) {
+            (ColorSupport::None, _) => Color::Reset,
+            (ColorSupport::Ansi256, Color::Rgb(r, g, b)) => nearest_indexed([r, g, b]),
+            (ColorSupport::Ansi16, Color::Rgb(..) | Color::Indexed(16..)) => {
+                rgb(color).map_or(color, nearest_ansi)
+            }
+            (ColorSupport::Ansi16, Color::Indexed(i)) => ANSI_COLORS[i as usize],
+            _ => color,
+        }
+    }
+}
+
+/// Colour a terminal would show, using the xterm palette, or `None` for the default.
+pub fn rgb(color: Color) -> Option<[u8; 3]> {
+    let rgb = match color {
+        Color::Reset => return None,
+        Color::Rgb(r, g, b) => [r, g, b],
+        Color::Indexed(i @ 0..=15) => ANSI[i as usize],
+        Color::Indexed(i @ 16..=231) => {
+            let i = (i - 16) as usize;
+            [
+                CUBE_LEVELS[i / 36],
+                CUBE_LEVELS[i / 6 % 6],
+                CUBE_LEVELS[i % 6],
+            ]
+        }
+        Color::Indexed(i) => {
+            let v = 8 + (i - 232) * 10;
+            [v, v, v]
+        }
+        named => ANSI[ANSI_COLORS.iter().position(|&c| c == named)?],
+    };
+    Some(rgb)
+}
+
+pub(crate) fn distance(a: [u8; 3], b: [u8; 3]) -> u32 {
+    a.iter()
+        .zip(&b)
+        .map(|(&a, &b)| (a as i32 - b as i32).pow(2) as u32)
+        .sum()
+}
+
+/// Nearest entry of the 6x6x6 colour cube or the grey ramp of the 256 colour palette.
+fn nearest_indexed(target: [u8; 3]) -> Color {
+    let level = |v: u8| {
+        (0..CUBE_LEVELS.len())
+            .min_by_key(|&i| (CUBE_LEVELS[i] as i32 - v as i32).abs())
+            .unwrap_or(0)
+    };
+    let cube = 16 + 36 * level(target[0]) + 6 * level(target[1]) + level(target[2]);
+    let mean = target.iter().map(|&v| v as u32).sum::<u32>() / 3;
+    let grey = 232 + (mean.saturating_sub(3) / 10).min(23) as usize;
+    [cube, grey]
+        .into_iter()
+        .map(|i| Color::Indexed(i as u8))
+        .min_by_key(|&c| rgb(c).map_or(u32::MAX, |c| distance(c, target)))
+        .unwrap_or(Color::Reset)
+}
+
+fn nearest_ansi(target: [u8; 3]) -> Color {
+    (0..ANSI.len())
+        .min_by_key(|&i| distance(ANSI[i], target))
+        .map_or(Color::Reset, |i| ANSI_COLORS[i])
+}
+
+/// Colours spread evenly from a level of 0 to 1. Neighbouring RGB colours blend, others
+/// change halfway between them.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Gradient(Vec<Color>);
+
+impl Gradient {
+    /// Panics if there are no colours.
+    pub fn new(colors: Vec<Color>) -> Self {
+        assert!(!colors.is_empty(), "a gradient needs at least one colour");
+        Self(colors)
+    }
+
+    pub fn solid(color: Color) -> Self {
+        Self(vec![color])
+    }
+
+    pub fn at(&self, level: f32) -> Color {
+        let stops = &self.0;
+        if stops.len() == 1 {
+            return stops[0];
+        }
+        let position = level.clamp(0.0, 1.0) * (stops.len() - 1) as f32;
+        let index = (position as usize).min(stops.len() - 2);
+        let t = position - index as f32;
+        match (stops[index], stops[index + 1]) {
+            (Color::Rgb(r0, g0, b0), Color::Rgb(r1, g1, b1)) => {
+                let mix = |a: u8, b: u8| (a as f32 + (b as f32 - a as f32) * t).round() as u8;
+                Color::Rgb(mix(r0, r1), mix(g0, g1), mix(b0, b1))
+            }
+            (from, _) if t < 0.5 => from,
+            (_, to) => to,
+        }
+    }
+}
+
+/// Colours of every part of the visualisation.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Theme {
+    /// Bars by level, from empty to full
+    pub bars: Gradient,
+    /// Value labels drawn on top of full bars
+    pub values: Color,
+    pub border: Color,
+    /// Clip indicators
+    pub clip: Color,
+    /// Spectrogram cells by level, from silent to loudest
+    pub heat: Gradient,
+    support: ColorSupport,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self {
+            bars: Gradient::new(vec![
+                Color::Rgb(0, 200, 0),
+                Color::Rgb(240, 210, 0),
+                Color::Rgb(230, 0, 0),
+            ]),
+            values: Color::Black,
+            border: Color::Reset,
+            clip: Color::Red,
+            heat: Gradient::new(vec![
+                Color::Rgb(0, 0, 0),
+                Color::Rgb(0, 0, 160),
+                Color::Rgb(200, 0, 60),
+                Color::Rgb(255, 200, 0),
+                Color::Rgb(255, 255, 255),
+            ]),
+            support: ColorSupport::TrueColor,
+        }
+    }
+}
+
+impl Theme {
+    /// Names accepted by [`Theme::named`].
+    pub const NAMES: [&'static str; 4] = ["default", "classic", "high-contrast", "mono"];
+
+    /// One of the built-in themes: `default` with bars going from green to red as they
+    /// get louder, `classic` with plain yellow bars, `high-contrast` using only bright
+    /// ANSI colours, or `mono` using no colour at all.
+    pub fn named(name: &str) -> Option<Self> {
+        let theme = match name {
+            "default" => Self::default(),
+            "classic" => Self {
+                bars: Gradient::solid(Color::Yellow),
+                ..Self::default()
+            },
+            "high-contrast" => Self {
+                bars: Gradient::new(vec![Color::LightGreen, Color::LightYellow, Color::LightRed]),
+                values: Color::Black,
+                border: Color::White,
+                clip: Color::LightRed,
+                heat: Gradient::new(vec![
+                    Color::Black,
+                    Color::Blue,
+                    Color::LightRed,
+                    Color::LightYellow,
+                    Color::White,
+                ]),
+                support: ColorSupport::TrueColor,
+            },
+            "mono" => Self::default().support(ColorSupport::None),
+            _ => return None,
+        };
+        Some(theme)
+    }
+
+    /// Limits the colours to what a terminal can show.
+    pub fn support(mut self, support: ColorSupport) -> Self {
+        self.support = self.support.min(support);
+        self
+    }
+
+    /// Whether everything is drawn in the default colours, so levels have to be told
+    /// apart by shape.
+    pub fn monochrome(&self) -> bool {
+        self.support == ColorSupport::None
+    }
+
+    /// Colour of a bar at a normalised level.
+    pub fn bar(&self, level: f32) -> Color {
+        self.support.fit(self.bars.at(level))
+    }
+
+    /// Style of a value label on a bar at a normalised level.
+    pub fn value_style(&self, level: f32) -> Style {
+        if self.monochrome() {
+            return Style::default().add_modifier(Modifier::REVERSED);
+        }
+        Style::default()
+            .fg(self.support.fit(self.values))
+            .bg(self.bar(level))
+    }
+
+    /// Colour of things other than levels, such as the waveform: the middle of the bar
+    /// gradient.
+    pub fn accent(&self) -> Color {
+        self.bar(0.5)
+    }
+
+    pub fn border_style(&self) -> Style {
+        Style::default().fg(self.support.fit(self.border))
+    }
+
+    /// Style of a clip warning: text on a block of the clip colour.
+    pub fn clip_style(&self) -> Style {
+        if self.monochrome() {
+            return Style::default().add_modifier(Modifier::REVERSED | Modifier::BOLD);
+        }
+        Style::default()
+            .fg(self.support.fit(Color::Black))
+            .bg(self.support.fit(self.clip))
+    }
+
+    /// Colour of a clipped peak marker.
+    pub fn clip_color(&self) -> Color {
+        self.support.fit(self.clip)
+    }
+
+    /// Colour of a spectrogram cell at a normalised level.
+    pub fn heat(&self, level: f32) -> Color {
+        self.support.fit(self.heat.at(level))
+    }
+}
@@ -0,0 +1,416 @@
+//! Ways of drawing the analysis, and the registry the visualiser cycles through.
+//!
+//! Each mode is a [`Visualizer`]. The built-in ones draw from the shared [`History`]; a
+//! library user can add their own with [`Registry::register`], which are then fed the
+//! same frames and picked with Tab or `--mode` like the others.
+
+use crate::{widget::channel_name, BarLayout, Frame, History, PeakMeter, Theme};
+use ratatui::{
+    buffer::Buffer,
+    layout::{Constraint, Direction, Layout, Rect},
+    style::Style,
+    symbols::Marker,
+    widgets::{Axis, BarChart, Chart, Dataset, GraphType, Paragraph, Widget},
+};
+
+/// One way of drawing the analysis.
+pub trait Visualizer {
+    /// Name shown in the chart title and matched by `--mode`, ignoring case.
+    fn name(&self) -> &str;
+
+    /// Called with every analysed frame, oldest first, for visualisers that keep state of
+    /// their own beyond the [`History`].
+    fn update(&mut self, _frame: &Frame) {}
+
+    /// Called when the frames stop following on from the earlier ones, e.g. after a seek
+    /// or at the start of a track.
+    fn clear(&mut self) {}
+
+    fn render(&self, scene: &Scene, area: Rect, buf: &mut Buffer);
+}
+
+/// What a [`Visualizer`] draws from, with helpers for drawing bars.
+pub struct Scene<'a> {
+    pub history: &'a History,
+    pub theme: &'a Theme,
+    /// Number of bars to aim for in a loudness history
+    pub bar_count: usize,
+    pub min_bar_width: u16,
+    pub max_bar_width: u16,
+}
+
+impl Scene<'_> {
+    /// Bars fitted to `area`, as many as `preferred` if the width limits allow.
+    pub fn layout(&self, area: Rect, preferred: usize) -> BarLayout {
+        BarLayout::fit(
+            area.width,
+            preferred,
+            self.min_bar_width,
+            self.max_bar_width,
+        )
+    }
+
+    /// Draws the latest levels of a history, as many as fit.
+    pub fn history_bars(&self, levels: &[f32], area: Rect, buf: &mut Buffer) {
+        let layout = self.layout(area, self.bar_count);
+        let shown = &levels[levels.len().saturating_sub(layout.count)..];
+        self.bars(shown, layout, area, buf);
+    }
+
+    /// Draws a spectrum stretched or squeezed to fill the width.
+    pub fn spectrum_bars(&self, spectrum: &[f32], area: Rect, buf: &mut Buffer) {
+        let layout = self.layout(area, spectrum.len());
+        let bands = spectrum.len();
+        let values: Vec<f32> = if bands == 0 {
+            Vec::new()
+        } else {
+            (0..layout.count)
+                .map(|bar| {
+                    // Loudest band under the bar, or the nearest one if bars outnumber bands
+                    let first = bar * bands / layout.count;
+                    let last = ((bar + 1) * bands / layout.count).max(first + 1);
+                    spectrum[first..last.min(bands)]
+                        .iter()
+                        .fold(0.0f32, |a, &b| a.max(b))
+                })
+                .collect()
+        };
+        self.bars(&values, layout, area, buf);
+    }
+
+    /// Draws one bar per normalised value, each coloured by its level.
+    pub fn bars(&self, values: &[f32], layout: BarLayout, area: Rect, buf: &mut Buffer) {
+        for (i, &level) in values.iter().enumerate() {
+            let x = area.x + i as u16 * (layout.width + layout.gap);
+            if x >= area.right() {
+                break;
+            }
+            let slot = Rect {
+                x,
+                width: layout.width.min(area.right() - x),
+                ..area
+            };
+            let label = i.to_string();
+            BarChart::default()
+                .data(&[(label.as_str(), (level * 100.0) as u64)])
+                .max(100)
+                .bar_width(layout.width)
+                .bar_gap(0)
+                .bar_style(Style::default().fg(self.theme.bar(level)))
+                .value_style(self.theme.value_style(level))
+                .render(slot, buf);
+        }
+    }
+}
+
+/// Visualisers to choose from, one of them current.
+pub struct Registry {
+    visualizers: Vec<Box<dyn Visualizer>>,
+    current: usize,
+}
+
+impl Default for Registry {
+    /// The built-in visualisers, starting with [`Levels`].
+    fn default() -> Self {
+        let mut registry = Self::new();
+        registry
+            .register(Levels)
+            .register(Spectrum)
+            .register(Channels)
+            .register(Scope)
+            .register(Meter)
+            .register(Spectrogram);
+        registry
+    }
+}
+
+impl Registry {
+    /// A registry without any visualisers, not even the built-in ones.
+    pub fn new() -> Self {
+        Self {
+            visualizers: Vec::new(),
+            current: 0,
+        }
+    }
+
+    /// Adds a visualiser after the others, or in place of one with the same name.
+    pub fn register(&mut self, visualizer: impl Visualizer + 'static) -> &mut Self {
+        let visualizer = Box::new(visualizer);
+        match self.position(visualizer.name()) {
+            Some(i) => self.visualizers[i] = visualizer,
+            None => self.visualizers.push(visualizer),
+        }
+        self
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.visualizers.iter().map(|v| v.name())
+    }
+
+    fn position(&self, name: &str) -> Option<usize> {
+        self.visualizers
+            .iter()
+            .position(|v| v.name().eq_ignore_ascii_case(name))
+    }
+
+    /// The visualiser with a name, ignoring case.
+    pub fn get(&self, name: &str) -> Option<&dyn Visualizer> {
+        self.position(name).map(|i| self.visualizers[i].as_ref())
+    }
+
+    /// Makes the visualiser with a name current, ignoring case. Returns false if there is
+    /// none.
+    pub fn select(&mut self, name: &str) -> bool {
+        let found = self.position(name);
+        if let Some(i) = found {
+            self.current = i;
+        }
+        found.is_some()
+    }
+
+    /// Moves on to the next visualiser, wrapping around.
+    pub fn next(&mut self) {
+        self.current = (self.current + 1) % self.visualizers.len().max(1);
+    }
+
+    /// The current visualiser. Panics if none are registered.
+    pub fn current(&self) -> &dyn Visualizer {
+        self.visualizers[self.current].as_ref()
+    }
+
+    /// Feeds a frame to every visualiser, so any can be switched to with its state up to
+    /// date.
+    pub fn update(&mut self, frame: &Frame) {
+        for visualizer in &mut self.visualizers {
+            visualizer.update(frame);
+        }
+    }
+
+    pub fn clear(&mut self) {
+        for visualizer in &mut self.visualizers {
+            visualizer.clear();
+        }
+    }
+}
+
+/// Loudness of the most recent chunks, oldest on the left.
+pub struct Levels;
+
+impl Visualizer for Levels {
+    fn name(&self) -> &str {
+        "Levels"
+    }
+
+    fn render(&self, scene: &Scene, area: Rect, buf: &mut Buffer) {
+        scene.history_bars(scene.history.levels(), area, buf);
+    }
+}
+
+/// Frequency content of the current chunk, low frequencies on the left.
+pub struct Spectrum;
+
+impl Visualizer for Spectrum {
+    fn name(&self) -> &str {
+        "Spectrum"
+    }
+
+    fn render(&self, scene: &Scene, area: Rect, buf: &mut Buffer) {
+        scene.spectrum_bars(scene.history.spectrum(), area, buf);
+    }
+}
+
+/// Loudness history of each channel, stacked, with a correlation/balance readout.
+pub struct Channels;
+
+impl Visualizer for Channels {
+    fn name(&self) -> &str {
+        "Channels"
+    }
+
+    fn render(&self, scene: &Scene, area: Rect, buf: &mut Buffer) {
+        let history = scene.history;
+        let channels = history.channels();
+        if channels == 0 || area.height < 2 {
+            return;
+        }
+
+        let rows = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints(
+                std::iter::once(Constraint::Length(1))
+                    .chain((0..channels).map(|_| Constraint::Ratio(1, channels as u32)))
+                    .collect::<Vec<_>>(),
+            )
+            .split(area);
+        Paragraph::new(readout(history.latest())).render(rows[0], buf);
+
+        for (channel, &row) in rows[1..].iter().enumerate() {
+            let columns = Layout::default()
+                .direction(Direction::Horizontal)
+                .constraints([Constraint::Length(2), Constraint::Min(0)].as_ref())
+                .split(row);
+            Paragraph::new(channel_name(channel, channels)).render(columns[0], buf);
+            scene.history_bars(history.channel_levels(channel), columns[1], buf);
+        }
+    }
+}
+
+/// Waveform of the current chunk, triggered on a rising zero crossing.
+pub struct Scope;
+
+impl Visualizer for Scope {
+    fn name(&self) -> &str {
+        "Scope"
+    }
+
+    fn render(&self, scene: &Scene, area: Rect, buf: &mut Buffer) {
+        // Show half the chunk so there is always a full span after the trigger point
+        let waveform = scene.history.waveform();
+        let span = waveform.len() / 2;
+        let start = trigger(&waveform[..span]);
+        let points: Vec<(f64, f64)> = waveform[start..start + span]
+            .iter()
+            .enumerate()
+            .map(|(i, &s)| (i as f64, s as f64))
+            .collect();
+
+        let dataset = Dataset::default()
+            .marker(Marker::Braille)
+            .graph_type(GraphType::Line)
+            .style(Style::default().fg(scene.theme.accent()))
+            .data(&points);
+        Chart::new(vec![dataset])
+            .x_axis(Axis::default().bounds([0.0, span.max(1) as f64 - 1.0]))
+            .y_axis(Axis::default().bounds([-1.0, 1.0]))
+            .render(area, buf);
+    }
+}
+
+/// Peak meter of each channel, see [`PeakMeter`].
+pub struct Meter;
+
+impl Visualizer for Meter {
+    fn name(&self) -> &str {
+        "Meter"
+    }
+
+    fn render(&self, scene: &Scene, area: Rect, buf: &mut Buffer) {
+        // Lie along the longer side, counting cells as twice as tall as wide
+        let direction = if area.height * 2 > area.width {
+            Direction::Vertical
+        } else {
+            Direction::Horizontal
+        };
+        PeakMeter::new(scene.history)
+            .direction(direction)
+            .theme(scene.theme.clone())
+            .render(area, buf)
+    }
+}
+
+/// Spectrum of the recent chunks over time, oldest on the left and low frequencies at the
+/// bottom, brighter for louder.
+pub struct Spectrogram;
+
+impl Visualizer for Spectrogram {
+    fn name(&self) -> &str {
+        "Spectrogram"
+    }
+
+    fn render(&self, scene: &Scene, area: Rect, buf: &mut Buffer) {
+        // Each cell shows two bands: the upper half as the foreground of a half block and
+        // the lower half as its background. The newest spectrum is at the right edge.
+        let theme = scene.theme;
+        let spectra = scene.history.spectra();
+        let columns = spectra.len().min(area.width as usize);
+        let rows = area.height as usize * 2;
+        for (i, spectrum) in spectra[spectra.len() - columns..].iter().enumerate() {
+            let x = area.right() - columns as u16 + i as u16;
+            let band = |row: usize| {
+                let index = row * spectrum.len() / rows;
+                spectrum.get(index).copied().unwrap_or(0.0)
+            };
+            for cell in 0..area.height {
+                let lower = cell as usize * 2;
+                let (upper, lower) = (band(lower + 1), band(lower));
+                let cell = buf.get_mut(x, area.bottom() - 1 - cell);
+                if theme.monochrome() {
+                    // Without colour both bands share a cell, shaded by the louder one
+                    cell.set_symbol(shade(upper.max(lower)));
+                } else {
+                    cell.set_symbol("▀")
+                        .set_style(Style::default().fg(theme.heat(upper)).bg(theme.heat(lower)));
+                }
+            }
+        }
+    }
+}
+
+/// Shade character for a normalised level, for drawing without colour.
+fn shade(level: f32) -> &'static str {
+    const SHADES: [&str; 5] = [" ", "░", "▒", "▓", "█"];
+    SHADES[((level.clamp(0.0, 1.0) * 4.0).round() as usize).min(4)]
+}
+
+/// Index of the first rising zero crossing, so periodic signals line up from one chunk to
+/// the next, or 0 if there is none.
+fn trigger(samples: &[f32]) -> usize {
+    samples
+        .windows(2)
+        .position(|pair| pair[0] < 0.0 && pair[1] >= 0.0)
+        .map_or(0, |i| i + 1)
+}
+
+/// One-line summary of the stereo image: phase correlation and left/right balance.
+fn readout(frame: &Frame) -> String {
+    match frame.channel_db.as_slice() {
+        [left, right, ..] => {
+            let difference = right - left;
+            let balance = if left.is_infinite() && right.is_infinite() {
+                "centre".to_string()
+            } else if left.is_infinite() {
+                "R only".to_string()
+            } else if right.is_infinite() {
+                "L only".to_string()
+            } else if difference.abs() < 0.05 {
+                "centre".to_string()
+            } else if difference > 0.0 {
+                format!("R +{difference:.1} dB")
+            } else {
+                format!("L +{:.1} dB", -difference)
+            };
+            format!("corr {:+.2}   bal {balance}", frame.correlation)
+        }
+        _ => "mono".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn balance(channel_db: &[f32]) -> String {
+        let frame = Frame {
+            channel_db: channel_db.to_vec(),
+            correlation: 1.0,
+            ..Frame::default()
+        };
+        readout(&frame)
+    }
+
+    #[test]
+    fn readout_shows_the_louder_side() {
+        assert_eq!(balance(&[-6.0, -6.02]), "corr +1.00   bal centre");
+        assert_eq!(balance(&[-6.0, -9.0]), "corr +1.00   bal L +3.0 dB");
+        assert_eq!(balance(&[-9.0, -6.0]), "corr +1.00   bal R +3.0 dB");
+        assert_eq!(balance(&[-6.0]), "mono");
+    }
+
+    #[test]
+    fn readout_tells_silence_from_hard_panning() {
+        let silent = f32::NEG_INFINITY;
+        assert_eq!(balance(&[silent, silent]), "corr +1.00   bal centre");
+        assert_eq!(balance(&[-6.0, silent]), "corr +1.00   bal L only");
+        assert_eq!(balance(&[silent, -6.0]), "corr +1.00   bal R only");
+    }
+}
@@ -0,0 +1,518 @@
+use crate::{
+    config::{Pane, PaneKind},
+    visualizer::{Levels, Scene, Visualizer},
+    History, Registry, Theme,
+};
+use ratatui::{
+    buffer::Buffer,
+    layout::{Alignment, Constraint, Direction, Layout, Rect},
+    style::Style,
+    text::{Span, Spans},
+    widgets::{Block, Paragraph, Widget},
+};
+use std::time::Duration;
+
+/// How a row of bars fits into a given width.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BarLayout {
+    pub count: usize,
+    pub width: u16,
+    pub gap: u16,
+}
+
+impl BarLayout {
+    /// Lays bars out across `available` cells, as wide as `preferred` bars would be
+    /// within the width limits, then as many of them as fit. Bars three or more cells
+    /// wide get a one cell gap.
+    pub fn fit(available: u16, preferred: usize, min_width: u16, max_width: u16) -> Self {
+        let min_width = min_width.max(1);
+        let slot = (available as usize / preferred.max(1)).clamp(1, u16::MAX as usize) as u16;
+        let gap = u16::from(slot >= 3 && slot > min_width);
+        let width = (slot - gap).clamp(min_width, max_width.max(min_width));
+        Self {
+            count: (available as usize + gap as usize) / (width as usize + gap as usize),
+            width,
+            gap,
+        }
+    }
+}
+
+/// Chart of a [`History`], drawn by a [`Visualizer`] inside an optional block.
+#[derive(Clone)]
+pub struct LevelChart<'a> {
+    history: &'a History,
+    visualizer: &'a dyn Visualizer,
+    block: Option<Block<'a>>,
+    theme: Theme,
+    bar_count: usize,
+    min_bar_width: u16,
+    max_bar_width: u16,
+}
+
+impl<'a> LevelChart<'a> {
+    pub fn new(history: &'a History) -> Self {
+        Self {
+            history,
+            visualizer: &Levels,
+            block: None,
+            theme: Theme::default(),
+            bar_count: 100,
+            min_bar_width: 1,
+            max_bar_width: 1,
+        }
+    }
+
+    /// Number of bars to aim for in the loudness histories. Fewer are shown if they would
+    /// be narrower than the minimum width, more if wider than the maximum.
+    pub fn bar_count(mut self, count: usize) -> Self {
+        self.bar_count = count;
+        self
+    }
+
+    /// Range of bar widths in cells.
+    pub fn bar_widths(mut self, min: u16, max: u16) -> Self {
+        self.min_bar_width = min;
+        self.max_bar_width = max;
+        self
+    }
+
+    pub fn visualizer(mut self, visualizer: &'a dyn Visualizer) -> Self {
+        self.visualizer = visualizer;
+        self
+    }
+
+    pub fn block(mut self, block: Block<'a>) -> Self {
+        self.block = Some(block);
+        self
+    }
+
+    pub fn theme(mut self, theme: Theme) -> Self {
+        self.theme = theme;
+        self
+    }
+}
+
+impl Widget for LevelChart<'_> {
+    fn render(mut self, area: Rect, buf: &mut Buffer) {
+        let area = match self.block.take() {
+            Some(block) => {
+                let inner = block.inner(area);
+                block.render(area, buf);
+                inner
+            }
+            None => area,
+        };
+
+        let scene = Scene {
+            history: self.history,
+            theme: &self.theme,
+            bar_count: self.bar_count,
+            min_bar_width: self.min_bar_width,
+            max_bar_width: self.max_bar_width,
+        };
+        self.visualizer.render(&scene, area, buf);
+    }
+}
+
+/// A [`Pane`] split up as it describes, with a copy of a [`LevelChart`] drawing each
+/// visualiser in it. The chart's block, if any, is titled with the visualiser's name.
+pub struct Panes<'a> {
+    pane: &'a Pane,
+    visualizers: &'a Registry,
+    chart: LevelChart<'a>,
+}
+
+impl<'a> Panes<'a> {
+    pub fn new(pane: &'a Pane, visualizers: &'a Registry, chart: LevelChart<'a>) -> Self {
+        Self {
+            pane,
+            visualizers,
+            chart,
+        }
+    }
+}
+
+impl Panes<'_> {
+    fn render_pane(&self, pane: &Pane, area: Rect, buf: &mut Buffer) {
+        match &pane.kind {
+            PaneKind::Visualizer(name) => {
+                // Unknown names are left blank; they are reported when the config is checked
+                if let Some(visualizer) = self.visualizers.get(name) {
+                    let mut chart = self.chart.clone().visualizer(visualizer);
+                    chart.block = chart.block.map(|block| block.title(visualizer.name()));
+                    chart.render(area, buf);
+                }
+            }
+            PaneKind::Split(direction, panes) => {
+                let sized: u16 = panes.iter().filter_map(|p| p.size).sum();
+                let shared = panes.iter().filter(|p| p.size.is_none()).count() as u32;
+                let constraints: Vec<Constraint> = panes
+                    .iter()
+                    .map(|pane| match pane.size {
+                        Some(size) => Constraint::Percentage(size),
+                        None => Constraint::Ratio(100 - sized.min(100) as u32, 100 * shared),
+                    })
+                    .collect();
+                let areas = Layout::default()
+                    .direction(direction.clone())
+                    .constraints(constraints)
+                    .split(area);
+                for (pane, &area) in panes.iter().zip(areas.iter()) {
+                    self.render_pane(pane, area, buf);
+                }
+            }
+        }
+    }
+}
+
+impl Widget for Panes<'_> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        self.render_pane(self.pane, area, buf);
+    }
+}
+
+/// Meter of the latest frame in a [`History`], one bar per channel: a solid bar up to the
+/// RMS level, a shaded one up to the sample peak and a decaying peak-hold marker. Above
+/// them are a readout with a clip counter that lights up once clipping has been seen, and
+/// the loudness in LUFS. Each cell of a bar is coloured by the level it stands for.
+pub struct PeakMeter<'a> {
+    history: &'a History,
+    direction: Direction,
+    block: Option<Block<'a>>,
+    theme: Theme,
+}
+
+impl<'a> PeakMeter<'a> {
+    pub fn new(history: &'a History) -> Self {
+        Self {
+            history,
+            direction: Direction::Vertical,
+            block: None,
+            theme: Theme::default(),
+        }
+    }
+
+    /// Which way the bars grow: upwards, or to the right.
+    pub fn direction(mut self, direction: Direction) -> Self {
+        self.direction = direction;
+        self
+    }
+
+    pub fn block(mut self, block: Block<'a>) -> Self {
+        self.block = Some(block);
+        self
+    }
+
+    pub fn theme(mut self, theme: Theme) -> Self {
+        self.theme = theme;
+        self
+    }
+}
+
+impl PeakMeter<'_> {
+    fn render_bar(&self, channel: usize, area: Rect, buf: &mut Buffer) {
+        let frame = self.history.latest();
+        let rms = frame.channel_levels.get(channel).copied().unwrap_or(0.0);
+        let peak = frame
+            .channel_peak_levels
+            .get(channel)
+            .copied()
+            .unwrap_or(0.0);
+        let hold = self.history.peak_hold(channel);
+
+        let (length, marker) = match self.direction {
+            Direction::Vertical => (area.height, "─"),
+            Direction::Horizontal => (area.width, "│"),
+        };
+        if length == 0 {
+            return;
+        }
+        let cells = length as f32;
+        let hold_cell = ((hold * cells) as u16).min(length - 1);
+        let clipped = hold >= 1.0;
+
+        for i in 0..length {
+            let start = i as f32 / cells;
+            let end = (i + 1) as f32 / cells;
+            let color = self.theme.bar(start);
+            let (symbol, color) = if hold > 0.0 && i == hold_cell {
+                (
+                    marker,
+                    if clipped {
+                        self.theme.clip_color()
+                    } else {
+                        color
+                    },
+                )
+            } else if end <= rms {
+                ("█", color)
+            } else if start < peak {
+                ("▒", color)
+            } else {
+                continue;
+            };
+
+            let style = Style::default().fg(color);
+            match self.direction {
+                Direction::Vertical => {
+                    let y = area.bottom() - 1 - i;
+                    for x in area.left()..area.right() {
+                        buf.get_mut(x, y).set_symbol(symbol).set_style(style);
+                    }
+                }
+                Direction::Horizontal => {
+                    let x = area.left() + i;
+                    for y in area.top()..area.bottom() {
+                        buf.get_mut(x, y).set_symbol(symbol).set_style(style);
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Widget for PeakMeter<'_> {
+    fn render(mut self, area: Rect, buf: &mut Buffer) {
+        let area = match self.block.take() {
+            Some(block) => {
+                let inner = block.inner(area);
+                block.render(area, buf);
+                inner
+            }
+            None => area,
+        };
+
+        let channels = self.history.channels();
+        if channels == 0 || area.height < 3 {
+            return;
+        }
+
+        let rows = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints(
+                [
+                    Constraint::Length(1),
+                    Constraint::Length(1),
+                    Constraint::Min(0),
+                ]
+                .as_ref(),
+            )
+            .split(area);
+        let frame = self.history.latest();
+        let clips = self.history.clips();
+        let clip = if clips > 0 {
+            Span::styled(format!(" CLIP {clips} "), self.theme.clip_style())
+        } else {
+            Span::raw(" clip 0 ")
+        };
+        let readout = Spans::from(vec![
+            Span::raw(format!(
+                "peak {:.1} dB   rms {:.1} dB  ",
+                frame.peak, frame.db
+            )),
+            clip,
+        ]);
+        Paragraph::new(readout).render(rows[0], buf);
+        let loudness = frame.loudness;
+        Paragraph::new(format!(
+            "M {:.1}  S {:.1}  I {:.1} LUFS   LRA {:.1} LU   TP {:.1} dBTP",
+            loudness.momentary,
+            loudness.short_term,
+            loudness.integrated,
+            loudness.range,
+            loudness.true_peak
+        ))
+        .render(rows[1], buf);
+
+        let bars = Layout::default()
+            .direction(match self.direction {
+                Direction::Vertical => Direction::Horizontal,
+                Direction::Horizontal => Direction::Vertical,
+            })
+            .constraints(
+                (0..channels)
+                    .map(|_| Constraint::Ratio(1, channels as u32))
+                    .collect::<Vec<_>>(),
+            )
+            .split(rows[2]);
+        for (channel, &area) in bars.iter().enumerate() {
+            // Label below a vertical bar, to the left of a horizontal one, with a gap
+            // between neighbouring bars
+            let (label, bar) = match self.direction {
+                Direction::Vertical => {
+                    let parts = Layout::default()
+                        .direction(Direction::Vertical)
+                        .constraints([Constraint::Min(0), Constraint::Length(1)].as_ref())
+                        .split(area);
+                    let bar = Rect {
+                        width: area.width.saturating_sub(1).max(1),
+                        ..parts[0]
+                    };
+                    (parts[1], bar)
+                }
+                Direction::Horizontal => {
+                    let parts = Layout::default()
+                        .direction(Direction::Horizontal)
+                        .constraints([Constraint::Length(2), Constraint::Min(0)].as_ref())
+                        .split(area);
+                    let bar = Rect {
+                        height: area.height.saturating_sub(1).max(1),
+                        ..parts[1]
+                    };
+                    (parts[0], bar)
+                }
+            };
+            Paragraph::new(channel_name(channel, channels)).render(label, buf);
+            self.render_bar(channel, bar, buf);
+        }
+    }
+}
+
+/// One line of playback details: the stream format and position on the left and the
+/// current level on the right.
+pub struct StatusBar<'a> {
+    sample_rate: u32,
+    channels: u16,
+    codec: Option<&'a str>,
+    elapsed: Option<Duration>,
+    total: Option<Duration>,
+    paused: bool,
+    db: f32,
+}
+
+impl<'a> StatusBar<'a> {
+    pub fn new(sample_rate: u32, channels: u16) -> Self {
+        Self {
+            sample_rate,
+            channels,
+            codec: None,
+            elapsed: None,
+            total: None,
+            paused: false,
+            db: f32::NEG_INFINITY,
+        }
+    }
+
+    pub fn codec(mut self, codec: Option<&'a str>) -> Self {
+        self.codec = codec;
+        self
+    }
+
+    /// Playback position, and the length of the stream if known to show the time left.
+    pub fn time(mut self, elapsed: Duration, total: Option<Duration>) -> Self {
+        self.elapsed = Some(elapsed);
+        self.total = total;
+        self
+    }
+
+    pub fn paused(mut self, paused: bool) -> Self {
+        self.paused = paused;
+        self
+    }
+
+    /// Current RMS level in dBFS.
+    pub fn db(mut self, db: f32) -> Self {
+        self.db = db;
+        self
+    }
+
+    /// Fraction of the stream played so far, if its length is known.
+    pub fn progress(&self) -> Option<f64> {
+        let (elapsed, total) = (self.elapsed?, self.total?);
+        (!total.is_zero()).then(|| (elapsed.as_secs_f64() / total.as_secs_f64()).clamp(0.0, 1.0))
+    }
+}
+
+impl Widget for StatusBar<'_> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let channels = match self.channels {
+            1 => "mono".to_string(),
+            2 => "stereo".to_string(),
+            n => format!("{n} ch"),
+        };
+        let mut details = format!("{} kHz  {channels}", self.sample_rate as f32 / 1000.0);
+        if let Some(codec) = self.codec {
+            details += &format!("  {codec}");
+        }
+        if let Some(elapsed) = self.elapsed {
+            details += &format!("   {}", clock(elapsed));
+            if let Some(total) = self.total {
+                let remaining = total.saturating_sub(elapsed);
+                details += &format!(" / {}  -{}", clock(total), clock(remaining));
+            }
+        }
+        if self.paused {
+            details += "  (paused)";
+        }
+
+        Paragraph::new(details).render(area, buf);
+        Paragraph::new(format!("{:.1} dB", self.db))
+            .alignment(Alignment::Right)
+            .render(area, buf);
+    }
+}
+
+/// A duration as m:ss, or h:mm:ss from an hour.
+fn clock(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    let (hours, minutes, seconds) = (seconds / 3600, seconds / 60 % 60, seconds % 60);
+    if hours > 0 {
+        format!("{hours}:{minutes:02}:{seconds:02}")
+    } else {
+        format!("{minutes}:{seconds:02}")
+    }
+}
+
+pub(crate) fn channel_name(channel: usize, channels: usize) -> String {
+    match (channels, channel) {
+        (1, _) => "M".to_string(),
+        (2, 0) => "L".to_string(),
+        (2, 1) => "R".to_string(),
+        _ => (channel + 1).to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Frame;
+
+    #[test]
+    fn bars_fit_the_width() {
+        let fit = |available, preferred, min, max| {
+            let layout = BarLayout::fit(available, preferred, min, max);
+            (layout.count, layout.width, layout.gap)
+        };
+        assert_eq!(fit(100, 100, 1, 10), (100, 1, 0));
+        assert_eq!(fit(100, 20, 1, 10), (20, 4, 1));
+        assert_eq!(fit(100, 5, 1, 10), (9, 10, 1));
+        assert_eq!(fit(10, 100, 2, 10), (5, 2, 0));
+        assert_eq!(fit(0, 10, 1, 10), (0, 1, 0));
+        assert_eq!(fit(u16::MAX, 1, 1, u16::MAX), (1, u16::MAX - 1, 1));
+    }
+
+    fn text(widget: impl Widget, width: u16, height: u16) -> String {
+        let area = Rect::new(0, 0, width, height);
+        let mut buffer = Buffer::empty(area);
+        widget.render(area, &mut buffer);
+        buffer.content().iter().map(|c| c.symbol.as_str()).collect()
+    }
+
+    #[test]
+    fn no_frame_reads_as_silence() {
+        let mut history = History::new(10);
+        let status = text(StatusBar::new(44100, 2).db(history.latest().db), 60, 1);
+        assert!(status.trim_end().ends_with("-inf dB"), "{status}");
+        assert_eq!(history.latest().peak, f32::NEG_INFINITY);
+
+        // Nor after the history is cleared, e.g. by a seek
+        history.push(Frame {
+            db: -12.0,
+            peak: -6.0,
+            ..Frame::default()
+        });
+        history.clear();
+        let status = text(StatusBar::new(44100, 2).db(history.latest().db), 60, 1);
+        assert!(status.trim_end().ends_with("-inf dB"), "{status}");
+    }
+}